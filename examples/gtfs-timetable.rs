@@ -1,15 +1,16 @@
-// Usage: cargo run --example gtfs-timetable <path_to_zip> <start_stop> <target_stop>
+// Usage: cargo run --example gtfs-timetable <path_to_zip> <start_stop> <target_stop> [service_date]
 
+use chrono::NaiveDate;
 use gtfs_structures::Gtfs;
 use humantime::format_duration;
-use raptor::{gtfs::GtfsTimetable, Journey, Timetable};
+use raptor::{gtfs::{GtfsTimetable, TripRun}, Journey, Leg, Tau};
 use std::{env, time::Duration};
 
 fn main() -> anyhow::Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 4 {
+    if !(4..=5).contains(&args.len()) {
         eprintln!(
-            "Usage: {} <path_to_zip> <start_stop> <target_stop>",
+            "Usage: {} <path_to_zip> <start_stop> <target_stop> [service_date]",
             args[0]
         );
         std::process::exit(1);
@@ -21,7 +22,12 @@ fn main() -> anyhow::Result<()> {
 
     // Load GTFS
     let gtfs = Gtfs::new(path)?;
-    let timetable = GtfsTimetable::new(&gtfs);
+    let mut timetable = GtfsTimetable::new(&gtfs);
+
+    // Only use the trips running on the given date (YYYY-MM-DD)
+    if let Some(date) = args.get(4) {
+        timetable.set_service_date(date.parse::<NaiveDate>()?);
+    }
 
     // Resolve stop IDs to internal indices
     let start = timetable
@@ -31,9 +37,9 @@ fn main() -> anyhow::Result<()> {
         .lookup_stop(target_stop_id)
         .ok_or_else(|| anyhow::anyhow!("Target stop '{}' not found", target_stop_id))?;
 
-    // Run RAPTOR (depart at 19:15)
+    // Run RAPTOR (depart at 19:15), from and to any platform of stations
     let departure_time = 19 * 3600 + 15 * 60;
-    let journeys = timetable.raptor(10, departure_time, start, target);
+    let journeys = timetable.station_raptor(10, departure_time, start, target);
 
     if journeys.is_empty() {
         println!("No journeys found.");
@@ -48,53 +54,77 @@ fn main() -> anyhow::Result<()> {
             i + 1,
             format_duration(travel_time)
         );
-        print_journey(&timetable, &gtfs, journey, target);
+        print_journey(&timetable, &gtfs, &timetable.station_journey(journey));
         println!();
     }
 
     Ok(())
 }
 
-fn print_journey(timetable: &GtfsTimetable, gtfs: &Gtfs, journey: &Journey<usize, usize>, target: usize) {
-    // Format: "stop_name" -["route_name"]-> "stop_name" ...
-    let plan = &journey.plan;
-
-    for (i, (route, boarding_stop)) in plan.iter().enumerate() {
-        let stop_id = timetable.resolve_stop(*boarding_stop).unwrap();
-        let stop_name = gtfs
-            .stops
+fn print_journey(timetable: &GtfsTimetable, gtfs: &Gtfs, journey: &Journey<usize, TripRun, usize>) {
+    // Format: [hh:mm] "stop_name" -["route_name"]-> [hh:mm] "stop_name"
+    let stop_name = |stop: usize| {
+        let stop_id = timetable.resolve_stop(stop).unwrap();
+        gtfs.stops
             .get(stop_id)
             .and_then(|s| s.name.as_deref())
-            .unwrap_or(stop_id);
-
-        let route_id = timetable.resolve_route(*route).unwrap();
-        let route_name = gtfs
-            .routes
-            .get(route_id)
-            .and_then(|r| r.short_name.as_deref().or(r.long_name.as_deref()))
-            .unwrap_or(route_id);
-
-        // Print boarding stop
-        print!("\"{}\" -[\"{}\"]-> ", stop_name, route_name);
-
-        // Alighting stop: next boarding stop or target
-        let alight_stop = if i + 1 < plan.len() {
-            plan[i + 1].1
-        } else {
-            target
-        };
-
-        let alight_id = timetable.resolve_stop(alight_stop).unwrap();
-        let alight_name = gtfs
-            .stops
-            .get(alight_id)
-            .and_then(|s| s.name.as_deref())
-            .unwrap_or(alight_id);
-
-        if i + 1 == plan.len() {
-            println!("\"{}\"", alight_name);
-        } else {
-            print!("\"{}\" ", alight_name);
+            .unwrap_or(stop_id)
+    };
+
+    for leg in &journey.legs {
+        match *leg {
+            Leg::Transit {
+                route,
+                trip,
+                board_stop,
+                board_time,
+                alight_stop,
+                alight_time,
+            } => {
+                let route_id = timetable.resolve_route(route).unwrap();
+                let route_name = gtfs
+                    .routes
+                    .get(route_id)
+                    .and_then(|r| r.short_name.as_deref().or(r.long_name.as_deref()))
+                    .unwrap_or(route_id);
+                let trip_id = timetable.resolve_trip(trip.trip).unwrap();
+
+                println!(
+                    "  [{}] \"{}\" -[\"{}\" ({})]-> [{}] \"{}\"",
+                    clock(board_time),
+                    stop_name(board_stop),
+                    route_name,
+                    trip_id,
+                    clock(alight_time),
+                    stop_name(alight_stop)
+                );
+            }
+            Leg::Walk { from, to, duration } => {
+                println!(
+                    "  \"{}\" -[walk {}]-> \"{}\"",
+                    stop_name(from),
+                    format_duration(Duration::from_secs(duration as u64)),
+                    stop_name(to)
+                );
+            }
+            Leg::Access { to, duration } => {
+                println!(
+                    "  -[walk {}]-> \"{}\"",
+                    format_duration(Duration::from_secs(duration as u64)),
+                    stop_name(to)
+                );
+            }
+            Leg::Egress { from, duration } => {
+                println!(
+                    "  \"{}\" -[walk {}]->",
+                    stop_name(from),
+                    format_duration(Duration::from_secs(duration as u64))
+                );
+            }
         }
     }
 }
+
+fn clock(tau: Tau) -> String {
+    format!("{:02}:{:02}", tau / 3600, (tau % 3600) / 60)
+}
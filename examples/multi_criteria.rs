@@ -0,0 +1,103 @@
+use raptor::{
+    Tau, Timetable,
+    mc::{Fare, Ride, VehicleChanges, WalkingTime},
+};
+
+// Two ways of getting from S to T:
+// - the bus goes straight to T, arriving at t=100
+// - the train goes to X, arriving at t=40, from where T is a 30s walk away
+//
+// Plain RAPTOR only ever returns the faster train + walk, while McRAPTOR with walking time as an
+// additional criterion also returns the bus.
+struct BusOrTrain;
+
+impl Timetable for BusOrTrain {
+    type Stop = char;
+    type Route = &'static str;
+    type Trip = &'static str;
+
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> {
+        let routes: &[_] = match stop {
+            'S' => &["bus", "train"],
+            'T' => &["bus"],
+            'X' => &["train"],
+            _ => &[],
+        };
+        routes.iter().copied()
+    }
+
+    fn get_earlier_stop(
+        &self,
+        _route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Self::Stop {
+        // S is the first stop of both routes
+        if right == 'S' { right } else { left }
+    }
+
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> {
+        let order: &[char] = match route {
+            "bus" => &['S', 'T'],
+            _ => &['S', 'X'],
+        };
+        let pos = order.iter().position(|&s| s == stop).unwrap_or(order.len());
+        order[pos..].iter().copied()
+    }
+
+    fn get_earliest_trip(
+        &self,
+        route: Self::Route,
+        at: Tau,
+        stop: Self::Stop,
+    ) -> Option<Self::Trip> {
+        (at <= self.get_departure_time(route, stop)).then_some(route)
+    }
+
+    fn get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau {
+        match (trip, stop) {
+            (_, 'S') => 0,
+            ("bus", 'T') => 100,
+            ("train", 'X') => 40,
+            _ => Tau::MAX,
+        }
+    }
+
+    fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau {
+        self.get_arrival_time(trip, stop)
+    }
+
+    fn get_footpaths_from(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> {
+        (stop == 'X').then_some('T').into_iter()
+    }
+
+    fn get_transfer_time(&self, _from: Self::Stop, _to: Self::Stop) -> Tau {
+        30
+    }
+}
+
+fn main() {
+    let timetable = BusOrTrain;
+
+    println!("RAPTOR:");
+    for journey in timetable.raptor(3, 0, 'S', 'T') {
+        println!("  {journey:?}");
+    }
+
+    println!("McRAPTOR, less walking:");
+    for journey in timetable.mc_raptor(3, 0, 'S', 'T', &WalkingTime) {
+        println!("  {journey:?}");
+    }
+
+    // the train costs 3, the bus 2
+    let fare = Fare(|ride: &Ride<&str, &str, char>| if ride.route == "train" { 3 } else { 2 });
+
+    println!("McRAPTOR, walking, fare and vehicle changes:");
+    for journey in timetable.mc_raptor(3, 0, 'S', 'T', &(WalkingTime, fare, VehicleChanges)) {
+        println!("  {journey:?}");
+    }
+}
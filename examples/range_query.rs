@@ -0,0 +1,97 @@
+use raptor::{Tau, Timetable};
+
+// A local line calling at stops [0..5] every 10 minutes, taking 5 minutes between stops, and an
+// express from 0 to 4 that only runs twice an hour and takes 8 minutes.
+struct LocalAndExpress;
+
+const HEADWAY: Tau = 600;
+const HOP: Tau = 300;
+const EXPRESS: [Tau; 2] = [900, 2700];
+
+impl Timetable for LocalAndExpress {
+    type Stop = usize;
+
+    type Route = &'static str;
+
+    // (route, departure from the first stop)
+    type Trip = (&'static str, Tau);
+
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> {
+        let routes: &[_] = match stop {
+            0 | 4 => &["local", "express"],
+            _ => &["local"],
+        };
+        routes.iter().copied()
+    }
+
+    fn get_earlier_stop(
+        &self,
+        _route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Self::Stop {
+        left.min(right)
+    }
+
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> {
+        let stops: Vec<_> = match route {
+            "local" => (stop..5).collect(),
+            _ if stop == 0 => vec![0, 4],
+            _ => vec![4],
+        };
+        stops.into_iter()
+    }
+
+    fn get_earliest_trip(
+        &self,
+        route: Self::Route,
+        at: Tau,
+        stop: Self::Stop,
+    ) -> Option<Self::Trip> {
+        match route {
+            "local" => {
+                let offset = stop * HOP;
+                let start = at.saturating_sub(offset).div_ceil(HEADWAY) * HEADWAY;
+                Some(("local", start))
+            }
+            _ => EXPRESS
+                .into_iter()
+                .map(|start| ("express", start))
+                .find(|&trip| self.get_departure_time(trip, stop) >= at),
+        }
+    }
+
+    fn get_arrival_time(&self, (route, start): Self::Trip, stop: Self::Stop) -> Tau {
+        match route {
+            "local" => start + stop * HOP,
+            _ if stop == 0 => start,
+            _ => start + 480,
+        }
+    }
+
+    fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau {
+        self.get_arrival_time(trip, stop)
+    }
+
+    fn get_footpaths_from(&self, _stop: Self::Stop) -> impl Iterator<Item = Self::Stop> {
+        std::iter::empty()
+    }
+}
+
+fn main() {
+    let timetable = LocalAndExpress;
+
+    // every departure from stop 0 to stop 4 in the first hour
+    let journeys = timetable.range_raptor(3, 0..=3600, 0, 4);
+
+    for journey in &journeys {
+        println!(
+            "depart {:>4} arrive {:>4} via {:?}",
+            journey.departure, journey.arrival, journey.legs
+        );
+    }
+}
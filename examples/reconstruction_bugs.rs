@@ -65,14 +65,15 @@ impl Timetable for Issue1Timetable {
     type Route = &'static str;
     type Trip = u32;
 
-    fn get_routes_serving_stop(&self, stop: Self::Stop) -> Vec<Self::Route> {
-        match stop {
-            'S' => vec!["R1", "R2"],
-            'A' => vec!["R1", "R3"],
-            'B' => vec!["R2", "R3"],
-            'C' | 'D' => vec!["R3"],
-            _ => vec![],
-        }
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> {
+        let routes: &[_] = match stop {
+            'S' => &["R1", "R2"],
+            'A' => &["R1", "R3"],
+            'B' => &["R2", "R3"],
+            'C' | 'D' => &["R3"],
+            _ => &[],
+        };
+        routes.iter().copied()
     }
 
     fn get_earlier_stop(
@@ -92,15 +93,19 @@ impl Timetable for Issue1Timetable {
         order[l.min(r)]
     }
 
-    fn get_stops_after(&self, route: Self::Route, stop: Self::Stop) -> Vec<Self::Stop> {
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> {
         let order: &[char] = match route {
             "R1" => &['S', 'A'],
             "R2" => &['S', 'B'],
             "R3" => &['A', 'B', 'C', 'D'],
-            _ => return vec![],
+            _ => &[],
         };
         let pos = order.iter().position(|&c| c == stop).unwrap_or(0);
-        order[pos..].to_vec()
+        order[pos..].iter().copied()
     }
 
     // Trip IDs: 10 = R1, 20 = R2, 31 = R3/T1(late), 32 = R3/T2(early)
@@ -188,8 +193,8 @@ impl Timetable for Issue1Timetable {
         }
     }
 
-    fn get_footpaths_from(&self, _: Self::Stop) -> Vec<Self::Stop> {
-        vec![]
+    fn get_footpaths_from(&self, _: Self::Stop) -> impl Iterator<Item = Self::Stop> {
+        std::iter::empty()
     }
 }
 
@@ -10,8 +10,8 @@ impl Timetable for SingleRoute {
 
     type Trip = usize;
 
-    fn get_routes_serving_stop(&self, _stop: Self::Stop) -> Vec<Self::Route> {
-        vec![0]
+    fn get_routes_serving_stop(&self, _stop: Self::Stop) -> impl Iterator<Item = Self::Route> {
+        [0].into_iter()
     }
 
     fn get_earlier_stop(
@@ -23,11 +23,14 @@ impl Timetable for SingleRoute {
         left.min(right)
     }
 
-    fn get_stops_after(&self, _route: Self::Route, stop: Self::Stop) -> Vec<Self::Stop> {
-        if stop == 9 {
-            return vec![];
-        }
-        (stop..10).collect()
+    fn get_stops_after(
+        &self,
+        _route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> {
+        // nothing to ride to from the last stop
+        let end = if stop == 9 { stop } else { 10 };
+        stop..end
     }
 
     fn get_arrival_time(&self, _trip: Self::Trip, stop: Self::Stop) -> Tau {
@@ -38,8 +41,8 @@ impl Timetable for SingleRoute {
         (stop * 10) + 5
     }
 
-    fn get_footpaths_from(&self, _stop: Self::Stop) -> Vec<Self::Stop> {
-        vec![]
+    fn get_footpaths_from(&self, _stop: Self::Stop) -> impl Iterator<Item = Self::Stop> {
+        std::iter::empty()
     }
 
     fn get_earliest_trip(
@@ -58,4 +61,9 @@ fn main() {
     let journey = mock.raptor(10, 0, 0, 9);
 
     println!("{journey:#?}");
+
+    // earliest arrival at every stop of the route
+    let arrivals = mock.earliest_arrivals(10, 0, 0);
+
+    println!("{arrivals:?}");
 }
@@ -10,7 +10,7 @@ impl Timetable for TwoRoutes {
 
     type Trip = usize;
 
-    fn get_routes_serving_stop(&self, stop: Self::Stop) -> Vec<Self::Route> {
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> {
         let mut routes = vec![];
 
         if (0..10).contains(&stop) {
@@ -21,7 +21,7 @@ impl Timetable for TwoRoutes {
             routes.push("r1")
         }
 
-        routes
+        routes.into_iter()
     }
 
     fn get_earlier_stop(
@@ -42,10 +42,14 @@ impl Timetable for TwoRoutes {
         }
     }
 
-    fn get_stops_after(&self, route: Self::Route, stop: Self::Stop) -> Vec<Self::Stop> {
-        if route == "r0" {
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> {
+        let stops: Vec<_> = if route == "r0" {
             if stop == 9 {
-                return vec![];
+                return vec![].into_iter();
             }
             (stop..10).collect()
         } else {
@@ -53,7 +57,8 @@ impl Timetable for TwoRoutes {
             let stop_idx = routes.iter().position(|&a| a == stop).unwrap();
 
             routes[stop_idx..].to_vec()
-        }
+        };
+        stops.into_iter()
     }
 
     fn get_earliest_trip(
@@ -84,8 +89,8 @@ impl Timetable for TwoRoutes {
         self.get_arrival_time(trip, stop) + 5
     }
 
-    fn get_footpaths_from(&self, stop: Self::Stop) -> Vec<Self::Stop> {
-        if stop == 2 { vec![2] } else { vec![] }
+    fn get_footpaths_from(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> {
+        (stop == 2).then_some(2).into_iter()
     }
 }
 
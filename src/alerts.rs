@@ -0,0 +1,235 @@
+//! Closed stops and suspended routes, as announced by service alerts.
+//!
+//! A [`ClosedTimetable`] wraps a timetable and leaves out what the [`Closures`] close: routes
+//! aren't boarded or alighted from at closed stops, suspended routes aren't ridden at all, and a
+//! route can stop serving a single stop while it keeps calling at the others. Footpaths are kept,
+//! and every query of the wrapped timetable runs on the wrapper.
+
+use std::collections::BTreeSet;
+
+use crate::reverse::ReverseTimetable;
+use crate::workspace::IndexedTimetable;
+use crate::{Error, Tau, Timetable};
+
+/// Stops and routes out of service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Closures<Route, Stop> {
+    stops: BTreeSet<Stop>,
+    routes: BTreeSet<Route>,
+    route_stops: BTreeSet<(Route, Stop)>,
+}
+
+impl<Route, Stop> Default for Closures<Route, Stop> {
+    fn default() -> Self {
+        Self {
+            stops: BTreeSet::new(),
+            routes: BTreeSet::new(),
+            route_stops: BTreeSet::new(),
+        }
+    }
+}
+
+impl<Route: Ord + Copy, Stop: Ord + Copy> Closures<Route, Stop> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// No route is boarded or alighted from at `stop`
+    pub fn close_stop(&mut self, stop: Stop) {
+        self.stops.insert(stop);
+    }
+
+    /// `route` isn't ridden
+    pub fn suspend_route(&mut self, route: Route) {
+        self.routes.insert(route);
+    }
+
+    /// `route` doesn't call at `stop`, other routes still do
+    pub fn close_route_stop(&mut self, route: Route, stop: Stop) {
+        self.route_stops.insert((route, stop));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stops.is_empty() && self.routes.is_empty() && self.route_stops.is_empty()
+    }
+
+    /// Whether `route` can be boarded and alighted from at `stop`
+    pub fn serves(&self, route: Route, stop: Stop) -> bool {
+        !self.stops.contains(&stop)
+            && !self.routes.contains(&route)
+            && !self.route_stops.contains(&(route, stop))
+    }
+}
+
+/// A timetable without the stops and routes of its [`Closures`]
+pub struct ClosedTimetable<'a, T: Timetable + ?Sized> {
+    timetable: &'a T,
+    closures: &'a Closures<T::Route, T::Stop>,
+}
+
+impl<'a, T: Timetable + ?Sized> ClosedTimetable<'a, T> {
+    pub fn new(timetable: &'a T, closures: &'a Closures<T::Route, T::Stop>) -> Self {
+        Self {
+            timetable,
+            closures,
+        }
+    }
+
+    /// The timetable with the stops and routes of the closures
+    pub fn timetable(&self) -> &'a T {
+        self.timetable
+    }
+
+    pub fn closures(&self) -> &'a Closures<T::Route, T::Stop> {
+        self.closures
+    }
+}
+
+impl<T: Timetable + ?Sized> Clone for ClosedTimetable<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Timetable + ?Sized> Copy for ClosedTimetable<'_, T> {}
+
+impl<T: Timetable + ?Sized> Timetable for ClosedTimetable<'_, T> {
+    type Stop = T::Stop;
+    type Route = T::Route;
+    type Trip = T::Trip;
+
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> + '_ {
+        self.timetable
+            .get_routes_serving_stop(stop)
+            .filter(move |&route| self.closures.serves(route, stop))
+    }
+
+    fn get_earlier_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Self::Stop {
+        self.timetable.get_earlier_stop(route, left, right)
+    }
+
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.timetable
+            .get_stops_after(route, stop)
+            .filter(move |&stop| self.closures.serves(route, stop))
+    }
+
+    fn get_earliest_trip(
+        &self,
+        route: Self::Route,
+        at: Tau,
+        stop: Self::Stop,
+    ) -> Option<Self::Trip> {
+        self.timetable.get_earliest_trip(route, at, stop)
+    }
+
+    fn get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau {
+        self.timetable.get_arrival_time(trip, stop)
+    }
+
+    fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau {
+        self.timetable.get_departure_time(trip, stop)
+    }
+
+    fn get_footpaths_from(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.timetable.get_footpaths_from(stop)
+    }
+
+    fn get_transfer_time(&self, from: Self::Stop, to: Self::Stop) -> Tau {
+        self.timetable.get_transfer_time(from, to)
+    }
+
+    fn get_min_change_time(&self, stop: Self::Stop) -> Tau {
+        self.timetable.get_min_change_time(stop)
+    }
+
+    fn get_trip_transfer_time(
+        &self,
+        from: (Self::Route, Self::Trip, Self::Stop),
+        to: (Self::Route, Self::Trip, Self::Stop),
+    ) -> Option<Tau> {
+        self.timetable.get_trip_transfer_time(from, to)
+    }
+
+    fn check_stop(&self, stop: Self::Stop) -> Result<(), Error> {
+        self.timetable.check_stop(stop)
+    }
+
+    fn try_get_earlier_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Result<Self::Stop, Error> {
+        self.timetable.try_get_earlier_stop(route, left, right)
+    }
+
+    fn try_get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        Ok(self
+            .timetable
+            .try_get_stops_after(route, stop)?
+            .filter(move |&stop| self.closures.serves(route, stop)))
+    }
+
+    fn try_get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> Result<Tau, Error> {
+        self.timetable.try_get_arrival_time(trip, stop)
+    }
+
+    fn try_get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> Result<Tau, Error> {
+        self.timetable.try_get_departure_time(trip, stop)
+    }
+}
+
+impl<T: ReverseTimetable + ?Sized> ReverseTimetable for ClosedTimetable<'_, T> {
+    fn get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.timetable
+            .get_stops_before(route, stop)
+            .filter(move |&stop| self.closures.serves(route, stop))
+    }
+
+    fn try_get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        Ok(self
+            .timetable
+            .try_get_stops_before(route, stop)?
+            .filter(move |&stop| self.closures.serves(route, stop)))
+    }
+
+    fn get_latest_trip(&self, route: Self::Route, at: Tau, stop: Self::Stop) -> Option<Self::Trip> {
+        self.timetable.get_latest_trip(route, at, stop)
+    }
+
+    fn get_footpaths_to(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.timetable.get_footpaths_to(stop)
+    }
+}
+
+impl<T: IndexedTimetable + ?Sized> IndexedTimetable for ClosedTimetable<'_, T> {
+    fn stop_count(&self) -> usize {
+        self.timetable.stop_count()
+    }
+
+    fn route_count(&self) -> usize {
+        self.timetable.route_count()
+    }
+}
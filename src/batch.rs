@@ -0,0 +1,170 @@
+//! Many queries at once, for origin–destination matrices, with the `rayon` feature.
+//!
+//! Queries run on the rayon thread pool, every thread keeping a [`RaptorWorkspace`] across the
+//! queries it runs. A full matrix runs a single one-to-all search per source and departure, and
+//! reads the arrival at every target off its labels.
+
+use rayon::prelude::*;
+
+use crate::workspace::{IndexedTimetable, RaptorWorkspace, WorkspaceFor};
+use crate::{Error, K, Label, LabelStore, Tau};
+
+/// A query of a batch, from `source` to `target` departing at `departure`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OdQuery<Stop> {
+    pub source: Stop,
+    pub target: Stop,
+    pub departure: Tau,
+}
+
+/// The earliest arrival of a query, as the time from the departure and the number of trips
+/// taken to get there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TravelTime {
+    pub duration: Tau,
+    pub trips: K,
+}
+
+/// Travel times from every source to every target, for every departure time. `None` where the
+/// target can't be reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OdMatrix {
+    departures: usize,
+    sources: usize,
+    targets: usize,
+    cells: Vec<Option<TravelTime>>,
+}
+
+impl OdMatrix {
+    /// Travel time of the `departure`-th departure time from the `source`-th source to the
+    /// `target`-th target, in the order they were given. None if an index is out of range.
+    pub fn get(
+        &self,
+        departure: usize,
+        source: usize,
+        target: usize,
+    ) -> Option<Option<TravelTime>> {
+        self.row(departure, source)?.get(target).copied()
+    }
+
+    /// Travel times from the `source`-th source to every target, for the `departure`-th
+    /// departure time. None if an index is out of range.
+    pub fn row(&self, departure: usize, source: usize) -> Option<&[Option<TravelTime>]> {
+        if departure >= self.departures || source >= self.sources {
+            return None;
+        }
+
+        let start = (departure * self.sources + source) * self.targets;
+        Some(&self.cells[start..start + self.targets])
+    }
+}
+
+/// An [`IndexedTimetable`] that can be shared between threads, to run batches of queries
+pub trait BatchTimetable:
+    IndexedTimetable<Stop: Send + Sync, Route: Send + Sync, Trip: Send + Sync> + Sync
+{
+    /// Panics where [`BatchTimetable::try_batch_raptor`] returns an error
+    fn batch_raptor(
+        &self,
+        transfers: usize,
+        queries: &[OdQuery<Self::Stop>],
+    ) -> Vec<Option<TravelTime>> {
+        self.try_batch_raptor(transfers, queries)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// The earliest arrival of every query with at most `transfers` trips, in the order of
+    /// `queries`
+    fn try_batch_raptor(
+        &self,
+        transfers: usize,
+        queries: &[OdQuery<Self::Stop>],
+    ) -> Result<Vec<Option<TravelTime>>, Error> {
+        for query in queries {
+            self.check_stop(query.source)?;
+            self.check_stop(query.target)?;
+        }
+
+        queries
+            .par_iter()
+            .map_init(
+                || RaptorWorkspace::for_timetable(self),
+                |workspace, query| {
+                    let targets = [(query.target, 0)];
+                    let mut search = workspace.search(self, transfers, &targets);
+                    search.run(query.source, query.departure)?;
+
+                    Ok(travel_time(
+                        search.labels.get(query.target, transfers),
+                        query.departure,
+                    ))
+                },
+            )
+            .collect()
+    }
+
+    /// Panics where [`BatchTimetable::try_od_matrix`] returns an error
+    fn od_matrix(
+        &self,
+        transfers: usize,
+        sources: &[Self::Stop],
+        targets: &[Self::Stop],
+        departures: &[Tau],
+    ) -> OdMatrix {
+        self.try_od_matrix(transfers, sources, targets, departures)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// The earliest arrival with at most `transfers` trips from every source to every target, for
+    /// every departure time
+    fn try_od_matrix(
+        &self,
+        transfers: usize,
+        sources: &[Self::Stop],
+        targets: &[Self::Stop],
+        departures: &[Tau],
+    ) -> Result<OdMatrix, Error> {
+        for &stop in sources.iter().chain(targets) {
+            self.check_stop(stop)?;
+        }
+
+        let rows = (0..departures.len() * sources.len())
+            .into_par_iter()
+            .map_init(
+                || RaptorWorkspace::for_timetable(self),
+                |workspace: &mut WorkspaceFor<Self>, row| {
+                    let departure = departures[row / sources.len()];
+                    let mut search = workspace.search(self, transfers, &[]);
+                    search.run(sources[row % sources.len()], departure)?;
+
+                    Ok(targets
+                        .iter()
+                        .map(|&pt| travel_time(search.labels.get(pt, transfers), departure))
+                        .collect::<Vec<_>>())
+                },
+            )
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(OdMatrix {
+            departures: departures.len(),
+            sources: sources.len(),
+            targets: targets.len(),
+            cells: rows.into_iter().flatten().collect(),
+        })
+    }
+}
+
+impl<T> BatchTimetable for T where
+    T: IndexedTimetable<Stop: Send + Sync, Route: Send + Sync, Trip: Send + Sync> + Sync + ?Sized
+{
+}
+
+fn travel_time<Route, Trip, Stop>(
+    label: Option<(K, Label<Route, Trip, Stop>)>,
+    departure: Tau,
+) -> Option<TravelTime> {
+    label.map(|(trips, label)| TravelTime {
+        duration: label.arrival - departure,
+        trips,
+    })
+}
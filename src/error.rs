@@ -0,0 +1,29 @@
+use thiserror::Error;
+
+/// Errors of the fallible `try_*` variants of the [`crate::Timetable`] methods and queries, and
+/// of building timetables from a feed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("unknown stop {0}")]
+    UnknownStop(String),
+    #[error("unknown route {0}")]
+    UnknownRoute(String),
+    #[error("unknown trip {0}")]
+    UnknownTrip(String),
+    #[error("stop {stop} is not on route {route}")]
+    StopNotOnRoute { route: String, stop: String },
+    #[error("trip {trip} doesn't call at stop {stop}")]
+    TripNotAtStop { trip: String, stop: String },
+    #[error("invalid feed: {0}")]
+    InvalidFeed(String),
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("the timetable has no service date")]
+    MissingServiceDate,
+    #[error("invalid realtime feed: {0}")]
+    InvalidRealtime(String),
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("stale snapshot of {found}, expected {expected}")]
+    StaleSnapshot { found: String, expected: String },
+}
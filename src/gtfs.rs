@@ -1,130 +1,1093 @@
-use std::{collections::BTreeMap, sync::OnceLock};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap},
+    ops::{Deref, Range},
+    sync::Arc,
+};
 
-use gtfs_structures::Gtfs;
-use smallvec::SmallVec;
+use chrono::NaiveDate;
+use gtfs_structures::{ExactTimes, Exception, Gtfs, LocationType, TransferType};
 
-use crate::Timetable;
+use crate::reverse::ReverseTimetable;
+use crate::workspace::IndexedTimetable;
+use crate::{Error, JourneyFor, Timetable};
+
+mod prebuilt;
+#[cfg(feature = "realtime")]
+mod realtime;
+#[cfg(feature = "serde")]
+mod snapshot;
 
 type Route = usize;
-type Trip = usize;
 type Stop = usize;
+// (from, (to, min_transfer_time))
+type Footpath = (Stop, (Stop, Option<u32>));
+
+const DAY: crate::Tau = 24 * 3600;
+// walking time of the footpaths of transfers.txt without a min_transfer_time
+const DEFAULT_TRANSFER_TIME: u32 = 300;
+// mean radius of the earth, in meters
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// A trip as it runs on the service date of the timetable.
+///
+/// Trips of the previous service day can still be running after midnight (with times past
+/// 24:00:00), these are included with their times shifted back by a day.
+///
+/// Trips listed in `frequencies.txt` run once per headway, every run departing from the first stop
+/// at `start` and keeping the travel times of the trip's `stop_times.txt` entries. Without
+/// `exact_times`, `start` is a headway after the vehicle could leave at the earliest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TripRun {
+    pub trip: usize,
+    pub previous_day: bool,
+    pub start: Option<u32>,
+}
+
+/// A row of transfers.txt, with the route and trip columns that gtfs-structures doesn't read.
+///
+/// Rules apply to changing from a trip alighted at `from_stop_id` to a trip boarded at
+/// `to_stop_id`, restricted to the given routes and trips. Stops can be left out of in-seat
+/// transfers (types 4 and 5), which then happen at the last stop of `from_trip_id` and the first
+/// stop of `to_trip_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferRule {
+    pub from_stop_id: Option<String>,
+    pub to_stop_id: Option<String>,
+    pub from_route_id: Option<String>,
+    pub to_route_id: Option<String>,
+    pub from_trip_id: Option<String>,
+    pub to_trip_id: Option<String>,
+    pub transfer_type: TransferType,
+    pub min_transfer_time: Option<u32>,
+}
+
+/// A transfer rule of a stop, with its routes and trips resolved to indices
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Transfer {
+    from_stop: Stop,
+    // GTFS routes
+    from_route: Option<usize>,
+    to_route: Option<usize>,
+    from_trip: Option<usize>,
+    to_trip: Option<usize>,
+    // None if the transfer is forbidden
+    min_time: Option<u32>,
+}
+
+impl Transfer {
+    /// How specific the rule is, the most specific matching rule applies: trips over routes,
+    /// and routes over stops only
+    fn specificity(&self) -> u8 {
+        let side = |route: Option<usize>, trip: Option<usize>| match (route, trip) {
+            (_, Some(_)) => 3,
+            (Some(_), None) => 1,
+            (None, None) => 0,
+        };
+
+        side(self.from_route, self.from_trip) + side(self.to_route, self.to_trip)
+    }
+}
+
+/// Minimum time of a transfer of `transfer_type`, None if forbidden. In-seat transfers are made
+/// at no cost, and timed transfers are treated as a minimum time of 0: nothing holds the departing
+/// trip for a late one, or checks that it is the trip the transfer is timed with.
+fn transfer_min_time(transfer_type: TransferType, min_transfer_time: Option<u32>) -> Option<u32> {
+    match transfer_type {
+        TransferType::MinTime => Some(min_transfer_time.unwrap_or(0)),
+        TransferType::Impossible => None,
+        TransferType::Recommended | TransferType::Timed | TransferType::StayOnBoard => Some(0),
+        // left out of the rules, it only rules out in-seat transfers which are never assumed
+        TransferType::MustAlight => Some(0),
+    }
+}
+
+/// Great-circle distance in meters between two points given as (latitude, longitude) in degrees
+fn haversine(
+    (latitude, longitude): (f64, f64),
+    (other_latitude, other_longitude): (f64, f64),
+) -> f64 {
+    let (phi, other_phi) = (latitude.to_radians(), other_latitude.to_radians());
+    let d_phi = other_phi - phi;
+    let d_lambda = (other_longitude - longitude).to_radians();
+
+    let a =
+        (d_phi / 2.0).sin().powi(2) + phi.cos() * other_phi.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Shortest walking time from `from` to every stop reachable by walking `walks` within
+/// `max_walk` (Dijkstra)
+fn shortest_walks(
+    walks: &Adjacency<(Stop, u32)>,
+    from: Stop,
+    max_walk: u32,
+) -> BTreeMap<Stop, u32> {
+    let mut durations = BTreeMap::new();
+    if walks.get(from).is_empty() {
+        return durations;
+    }
+
+    let mut queue = BinaryHeap::from([Reverse((0, from))]);
+    while let Some(Reverse((duration, stop))) = queue.pop() {
+        if durations.contains_key(&stop) {
+            continue;
+        }
+        durations.insert(stop, duration);
+
+        for &(next, walk) in walks.get(stop) {
+            let duration = duration.saturating_add(walk);
+            if duration <= max_walk && !durations.contains_key(&next) {
+                queue.push(Reverse((duration, next)));
+            }
+        }
+    }
+
+    durations
+}
+
+/// Items grouped by index, stored contiguously: the items of index `i` are
+/// `items[offsets[i]..offsets[i + 1]]`
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Adjacency<T> {
+    offsets: Vec<usize>,
+    items: Vec<T>,
+}
+
+impl<T> Adjacency<T> {
+    /// Groups `pairs` of index and item, keeping the order of the items of every index
+    fn new(len: usize, pairs: impl IntoIterator<Item = (usize, T)>) -> Self {
+        let mut pairs: Vec<_> = pairs.into_iter().collect();
+        pairs.sort_by_key(|&(idx, _)| idx);
+
+        let mut offsets = vec![0; len + 1];
+        for &(idx, _) in &pairs {
+            offsets[idx + 1] += 1;
+        }
+        for idx in 0..len {
+            offsets[idx + 1] += offsets[idx];
+        }
+
+        Self {
+            offsets,
+            items: pairs.into_iter().map(|(_, item)| item).collect(),
+        }
+    }
+
+    fn get(&self, idx: usize) -> &[T] {
+        match (self.offsets.get(idx), self.offsets.get(idx + 1)) {
+            (Some(&start), Some(&end)) => &self.items[start..end],
+            _ => &[],
+        }
+    }
+}
+
+/// A RAPTOR route: trips of a GTFS route that call at the same sequence of stops, and never
+/// overtake each other
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Pattern {
+    // GTFS route the trips belong to
+    route: usize,
+    // range of `route_stops`
+    stops: Range<usize>,
+}
+
+/// Where a route calls at a stop
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct RouteStop {
+    route: Route,
+    // first position of the stop on the route
+    position: usize,
+}
+
+/// A trip of the realtime feed: a run of the schedule with realtime times, or a trip added to it
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct RealtimeTrip {
+    id: String,
+    // trip of the schedule whose run it replaces
+    scheduled: Option<usize>,
+}
+
+/// The routes, trips, footpaths and transfer rules of a [`GtfsTimetable`], with the runs of its
+/// service date. Queries run on it without the feed it was built from, whose ids it keeps.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrebuiltTimetable {
+    stops: Vec<String>,
+    trips: Vec<String>,
+    routes: Vec<String>,
+
+    service_date: Option<NaiveDate>,
 
-type RoutesForStops = BTreeMap<Stop, SmallVec<[Route; 8]>>;
-type StopForRoutes = BTreeMap<Route, SmallVec<[Stop; 32]>>;
-type TripsForRoutes = BTreeMap<Route, Vec<Trip>>;
+    patterns: Vec<Pattern>,
+    route_stops: Vec<Stop>,
+    // trips of every pattern, sorted by departure
+    pattern_trips: Adjacency<usize>,
+    // pattern of every trip, and where its stop times start in `stop_times`
+    trip_patterns: Vec<Route>,
+    trip_stop_times: Vec<usize>,
+    // (arrival, departure) of every trip at every stop of its pattern, as in stop_times.txt
+    stop_times: Vec<(u32, u32)>,
+    stop_routes: Adjacency<RouteStop>,
+    // (stop, min_transfer_time)
+    footpaths: Adjacency<(Stop, Option<u32>)>,
+    footpaths_to: Adjacency<Stop>,
+    // platforms of every station, and station of every platform
+    platforms: Adjacency<Stop>,
+    stations: Vec<Option<Stop>>,
+    // transfer rules by stop of the departing trip
+    transfers: Adjacency<Transfer>,
+    // minimum change time of the stops without a self-transfer in transfers.txt
+    min_change_time: u32,
+    // trips of the realtime feed, numbered after the trips of the schedule
+    realtime_trips: Vec<RealtimeTrip>,
 
+    // runs of every route on the service date
+    runs: Adjacency<TripRun>,
+    // whether the runs of every route keep their order at all of its stops, else they're
+    // searched one by one
+    runs_in_order: Vec<bool>,
+}
+
+/// The feed of a timetable, borrowed or shared with it
+#[derive(Clone)]
+enum Feed<'gtfs> {
+    Borrowed(&'gtfs Gtfs),
+    Shared(Arc<Gtfs>),
+}
+
+impl Deref for Feed<'_> {
+    type Target = Gtfs;
+
+    fn deref(&self) -> &Gtfs {
+        match self {
+            Feed::Borrowed(gtfs) => gtfs,
+            Feed::Shared(gtfs) => gtfs,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct GtfsTimetable<'gtfs> {
-    gtfs: &'gtfs Gtfs,
+    gtfs: Feed<'gtfs>,
 
-    stops: Vec<&'gtfs str>,
-    trips: Vec<&'gtfs str>,
-    routes: Vec<&'gtfs str>,
+    // (max_distance, walking_speed) of the footpaths generated between nearby stops
+    footpath_generation: Option<(f64, f64)>,
+    // walking time between the platforms of a station
+    station_transfer_time: Option<u32>,
+    // runs of the schedule replaced or cancelled by the realtime feed
+    cancelled_runs: BTreeSet<TripRun>,
 
-    // can use docs.rs/arc-swap's cache for realtime support
-    routes_for_stops: OnceLock<RoutesForStops>,
-    stops_for_routes: OnceLock<StopForRoutes>,
-    trips_for_routes: OnceLock<TripsForRoutes>,
+    prebuilt: PrebuiltTimetable,
 }
 
 impl<'a> GtfsTimetable<'a> {
+    /// Panics where [`GtfsTimetable::try_new`] returns an error
     pub fn new(gtfs: &'a Gtfs) -> Self {
-        let mut stops: Vec<_> = gtfs.stops.keys().map(String::as_str).collect();
-        stops.sort();
-        let mut routes: Vec<_> = gtfs.routes.keys().map(String::as_str).collect();
-        routes.sort();
-        let mut trips: Vec<_> = gtfs.trips.keys().map(String::as_str).collect();
-        trips.sort();
+        Self::try_new(gtfs).unwrap_or_else(|err| panic!("{err}"))
+    }
 
-        Self {
+    /// Preprocesses `gtfs` into a timetable, failing on trips that reference unknown routes
+    pub fn try_new(gtfs: &'a Gtfs) -> Result<Self, Error> {
+        Self::build(Feed::Borrowed(gtfs))
+    }
+
+    fn build(gtfs: Feed<'a>) -> Result<Self, Error> {
+        let ids = |ids: Vec<&String>| {
+            let mut ids: Vec<_> = ids.into_iter().cloned().collect();
+            ids.sort();
+            ids
+        };
+        let stops = ids(gtfs.stops.keys().collect());
+        let routes = ids(gtfs.routes.keys().collect());
+        let trips = ids(gtfs.trips.keys().collect());
+
+        let mut timetable = Self {
             gtfs,
-            stops,
-            routes,
-            trips,
-            routes_for_stops: Default::default(),
-            stops_for_routes: Default::default(),
-            trips_for_routes: Default::default(),
+            footpath_generation: None,
+            station_transfer_time: None,
+            cancelled_runs: BTreeSet::new(),
+            prebuilt: PrebuiltTimetable {
+                stops,
+                routes,
+                trips,
+                service_date: None,
+                patterns: Vec::new(),
+                route_stops: Vec::new(),
+                pattern_trips: Adjacency::new(0, []),
+                trip_patterns: Vec::new(),
+                trip_stop_times: Vec::new(),
+                stop_times: Vec::new(),
+                stop_routes: Adjacency::new(0, []),
+                footpaths: Adjacency::new(0, []),
+                footpaths_to: Adjacency::new(0, []),
+                platforms: Adjacency::new(0, []),
+                stations: Vec::new(),
+                transfers: Adjacency::new(0, []),
+                min_change_time: 0,
+                realtime_trips: Vec::new(),
+                runs: Adjacency::new(0, []),
+                runs_in_order: Vec::new(),
+            },
+        };
+        timetable.build_patterns()?;
+        timetable.build_stations();
+        timetable.build_footpaths();
+        timetable.set_transfer_rules(&[])?;
+        timetable.build_runs();
+
+        Ok(timetable)
+    }
+
+    /// The feed the timetable was built from
+    pub fn gtfs(&self) -> &Gtfs {
+        &self.gtfs
+    }
+
+    /// Only consider the trips running on `date`, see [`GtfsTimetable::set_service_date`]
+    pub fn with_service_date(mut self, date: NaiveDate) -> Self {
+        self.set_service_date(date);
+        self
+    }
+
+    /// Only consider the trips whose service is active on `date`, following calendar.txt and the
+    /// exceptions of calendar_dates.txt, along with the trips of the previous day still running
+    /// after midnight. Times are then counted from midnight of `date`.
+    ///
+    /// Without a service date, every trip of the feed is considered.
+    pub fn set_service_date(&mut self, date: NaiveDate) {
+        self.prebuilt.service_date = Some(date);
+        self.build_runs();
+    }
+
+    pub fn service_date(&self) -> Option<NaiveDate> {
+        self.prebuilt.service_date
+    }
+
+    /// Sets the minimum change time of every stop, see [`GtfsTimetable::set_min_change_time`]
+    pub fn with_min_change_time(mut self, min_change_time: u32) -> Self {
+        self.set_min_change_time(min_change_time);
+        self
+    }
+
+    /// Minimum time to change trips at the stops without a transfer to themselves in
+    /// transfers.txt, 0 by default
+    pub fn set_min_change_time(&mut self, min_change_time: u32) {
+        self.prebuilt.min_change_time = min_change_time;
+    }
+
+    /// Adds footpaths between nearby stops, see [`GtfsTimetable::generate_footpaths`]
+    pub fn with_generated_footpaths(
+        mut self,
+        max_distance: f64,
+        walking_speed: f64,
+    ) -> Result<Self, Error> {
+        self.generate_footpaths(max_distance, walking_speed)?;
+        Ok(self)
+    }
+
+    /// Connects the stops within `max_distance` meters of each other with footpaths, walked at
+    /// `walking_speed` meters per second, for feeds that lack transfers.txt. Footpaths of
+    /// transfers.txt are kept, and the generated footpaths are then closed transitively as RAPTOR
+    /// expects: stops reached by walking several footpaths, in no longer than it takes to walk
+    /// `max_distance`, get a direct footpath taking the shortest walk. Replaces the footpaths
+    /// generated before.
+    ///
+    /// Only stops and platforms with coordinates are connected. Fails, keeping the footpaths,
+    /// unless `max_distance` is at least 0 and `walking_speed` positive.
+    pub fn generate_footpaths(
+        &mut self,
+        max_distance: f64,
+        walking_speed: f64,
+    ) -> Result<(), Error> {
+        if max_distance.is_nan() || max_distance < 0.0 {
+            return Err(Error::InvalidConfig(format!(
+                "footpath distance {max_distance} isn't at least 0"
+            )));
         }
+        if walking_speed.is_nan() || walking_speed <= 0.0 {
+            return Err(Error::InvalidConfig(format!(
+                "walking speed {walking_speed} isn't positive"
+            )));
+        }
+
+        self.footpath_generation = Some((max_distance, walking_speed));
+        self.build_footpaths();
+        Ok(())
+    }
+
+    /// Connects the platforms of every station, see [`GtfsTimetable::set_station_transfer_time`]
+    pub fn with_station_transfer_time(mut self, transfer_time: u32) -> Self {
+        self.set_station_transfer_time(transfer_time);
+        self
     }
 
-    fn cache_routes_for_stops(&self) -> RoutesForStops {
-        let mut routes_for_stops = RoutesForStops::default();
+    /// Connects the platforms of every station with footpaths taking `transfer_time`, unless
+    /// transfers.txt has a footpath between them. These footpaths are closed transitively along
+    /// with the generated ones, see [`GtfsTimetable::generate_footpaths`].
+    pub fn set_station_transfer_time(&mut self, transfer_time: u32) {
+        self.station_transfer_time = Some(transfer_time);
+        self.build_footpaths();
+    }
 
-        for trip in self.gtfs.trips.values() {
-            let route = self.route_idx(&trip.route_id).unwrap();
-            for st in &trip.stop_times {
-                let stop = self.stop_idx(&st.stop.id).unwrap();
-                routes_for_stops.entry(stop).or_default().push(route);
+    /// Follows `rules` when changing trips, see [`GtfsTimetable::set_transfer_rules`]
+    pub fn with_transfer_rules(mut self, rules: &[TransferRule]) -> Result<Self, Error> {
+        self.set_transfer_rules(rules)?;
+        Ok(self)
+    }
+
+    /// Follows `rules` when changing trips, along with the transfers of transfers.txt between a
+    /// stop and itself, failing on rules that reference unknown stops, routes or trips. Replaces
+    /// the rules set before.
+    ///
+    /// gtfs-structures only reads the stop columns of transfers.txt, the rules with routes or
+    /// trips have to be read separately and given here.
+    pub fn set_transfer_rules(&mut self, rules: &[TransferRule]) -> Result<(), Error> {
+        let mut transfers = Vec::new();
+
+        for (stop, stop_id) in self.prebuilt.stops.iter().enumerate() {
+            for transfer in &self.gtfs.stops[stop_id].transfers {
+                // in-seat transfers need trips, which gtfs-structures doesn't read
+                let in_seat = matches!(
+                    transfer.transfer_type,
+                    TransferType::StayOnBoard | TransferType::MustAlight
+                );
+                if transfer.to_stop_id == *stop_id && !in_seat {
+                    let transfer = Transfer {
+                        from_stop: stop,
+                        from_route: None,
+                        to_route: None,
+                        from_trip: None,
+                        to_trip: None,
+                        min_time: transfer_min_time(
+                            transfer.transfer_type,
+                            transfer.min_transfer_time,
+                        ),
+                    };
+                    transfers.extend(self.expand_platforms(stop, transfer));
+                }
+            }
+        }
+
+        for rule in rules {
+            // must-alight rules only rule out in-seat transfers, which are never assumed
+            if rule.transfer_type == TransferType::MustAlight {
+                continue;
             }
+
+            let unknown = |kind: &str, id: &str| {
+                Error::InvalidFeed(format!("transfer rule references unknown {kind} {id}"))
+            };
+            let route = |id: &Option<String>| {
+                id.as_deref()
+                    .map(|id| {
+                        self.prebuilt
+                            .route_idx(id)
+                            .ok_or_else(|| unknown("route", id))
+                    })
+                    .transpose()
+            };
+            let trip = |id: &Option<String>| {
+                id.as_deref()
+                    .map(|id| {
+                        self.prebuilt
+                            .trip_idx(id)
+                            .ok_or_else(|| unknown("trip", id))
+                    })
+                    .transpose()
+            };
+            // stops default to where in-seat transfers happen
+            let stop = |id: &Option<String>, trip: Option<usize>, last: bool| {
+                let id = match (id, trip) {
+                    (Some(id), _) => id.as_str(),
+                    (None, Some(trip)) => {
+                        let stop_times = &self.gtfs.trips[&self.prebuilt.trips[trip]].stop_times;
+                        let stop_time = if last {
+                            stop_times.last()
+                        } else {
+                            stop_times.first()
+                        };
+                        match stop_time {
+                            Some(stop_time) => stop_time.stop.id.as_str(),
+                            None => {
+                                return Err(unknown("stop of trip", &self.prebuilt.trips[trip]));
+                            }
+                        }
+                    }
+                    (None, None) => {
+                        return Err(Error::InvalidFeed(
+                            "transfer rule has neither a stop nor a trip".to_string(),
+                        ));
+                    }
+                };
+                self.prebuilt
+                    .stop_idx(id)
+                    .ok_or_else(|| unknown("stop", id))
+            };
+
+            let from_trip = trip(&rule.from_trip_id)?;
+            let to_trip = trip(&rule.to_trip_id)?;
+            let transfer = Transfer {
+                from_stop: stop(&rule.from_stop_id, from_trip, true)?,
+                from_route: route(&rule.from_route_id)?,
+                to_route: route(&rule.to_route_id)?,
+                from_trip,
+                to_trip,
+                min_time: transfer_min_time(rule.transfer_type, rule.min_transfer_time),
+            };
+            let to_stop = stop(&rule.to_stop_id, to_trip, false)?;
+            transfers.extend(self.expand_platforms(to_stop, transfer));
         }
 
-        routes_for_stops
+        self.prebuilt.transfers = Adjacency::new(self.prebuilt.stops.len(), transfers);
+        Ok(())
     }
 
-    fn cache_stops_for_routes(&self) -> StopForRoutes {
-        let mut stops_for_routes = StopForRoutes::default();
+    /// A transfer rule to `to_stop` for every pair of platforms, when its stops are stations
+    fn expand_platforms(
+        &self,
+        to_stop: Stop,
+        transfer: Transfer,
+    ) -> impl Iterator<Item = (Stop, Transfer)> + '_ {
+        self.platforms(transfer.from_stop)
+            .flat_map(move |from_stop| {
+                self.platforms(to_stop).map(move |to_stop| {
+                    (
+                        to_stop,
+                        Transfer {
+                            from_stop,
+                            ..transfer
+                        },
+                    )
+                })
+            })
+    }
 
-        for trip in self.gtfs.trips.values() {
-            let route = self.route_idx(&trip.route_id).unwrap();
-            // TODO: handle case where multiple trips run on a route but with different patterns
-            // which require merging stops in a meaningful way
+    fn is_service_active(&self, service_id: &str, date: NaiveDate) -> bool {
+        let exception = self
+            .gtfs
+            .calendar_dates
+            .get(service_id)
+            .and_then(|dates| dates.iter().find(|cd| cd.date == date));
 
-            if stops_for_routes.contains_key(&route) {
+        match exception {
+            Some(cd) => cd.exception_type == Exception::Added,
+            None => self.gtfs.calendar.get(service_id).is_some_and(|calendar| {
+                calendar.start_date <= date
+                    && date <= calendar.end_date
+                    && calendar.valid_weekday(date)
+            }),
+        }
+    }
+
+    fn build_patterns(&mut self) -> Result<(), Error> {
+        // trips grouped by GTFS route and sequence of stops
+        let mut groups = BTreeMap::<(usize, Vec<Stop>), Vec<usize>>::new();
+
+        for (trip_idx, trip_id) in self.prebuilt.trips.iter().enumerate() {
+            let trip = &self.gtfs.trips[trip_id];
+            // trips without stop times can't be ridden
+            if trip.stop_times.is_empty() {
                 continue;
             }
 
-            for st in &trip.stop_times {
-                let stop = self.stop_idx(&st.stop.id).unwrap();
-                stops_for_routes.entry(route).or_default().push(stop);
+            let route = self.prebuilt.route_idx(&trip.route_id).ok_or_else(|| {
+                Error::InvalidFeed(format!(
+                    "trip {trip_id} references unknown route {}",
+                    trip.route_id
+                ))
+            })?;
+            let stops = trip
+                .stop_times
+                .iter()
+                .map(|st| {
+                    self.prebuilt.stop_idx(&st.stop.id).ok_or_else(|| {
+                        Error::InvalidFeed(format!(
+                            "trip {trip_id} references unknown stop {}",
+                            st.stop.id
+                        ))
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+
+            groups.entry((route, stops)).or_default().push(trip_idx);
+        }
+
+        // trips of every pattern
+        let mut patterns = Vec::<(Pattern, Vec<usize>)>::new();
+
+        for ((route, stops), mut trips) in groups {
+            trips.sort_by_key(|&trip| self.gtfs_first_departure(trip));
+
+            let start = self.prebuilt.route_stops.len();
+            self.prebuilt.route_stops.extend(stops);
+            let stops = start..self.prebuilt.route_stops.len();
+
+            // a trip overtaking the last trip of every pattern of this group so far starts a new
+            // pattern
+            let first = patterns.len();
+            for trip in trips {
+                let pattern = patterns[first..]
+                    .iter_mut()
+                    .find(|(_, trips)| !self.overtakes(trip, *trips.last().unwrap()));
+
+                match pattern {
+                    Some((_, trips)) => trips.push(trip),
+                    None => patterns.push((
+                        Pattern {
+                            route,
+                            stops: stops.clone(),
+                        },
+                        vec![trip],
+                    )),
+                }
+            }
+        }
+
+        self.prebuilt.trip_patterns = vec![0; self.prebuilt.trips.len()];
+        self.prebuilt.trip_stop_times = vec![0; self.prebuilt.trips.len()];
+        for (route, (_, trips)) in patterns.iter().enumerate() {
+            for &trip in trips {
+                self.prebuilt.trip_patterns[trip] = route;
+                self.prebuilt.trip_stop_times[trip] = self.prebuilt.stop_times.len();
+
+                // non-timepoints without times are given the last known time
+                let mut last = 0;
+                for st in &self.gtfs.trips[&self.prebuilt.trips[trip]].stop_times {
+                    let arrival = st.arrival_time.or(st.departure_time).unwrap_or(last);
+                    let departure = st.departure_time.unwrap_or(arrival);
+                    last = departure;
+
+                    self.prebuilt.stop_times.push((arrival, departure));
+                }
             }
         }
 
-        stops_for_routes
+        self.prebuilt.pattern_trips = Adjacency::new(
+            patterns.len(),
+            patterns
+                .iter()
+                .enumerate()
+                .flat_map(|(route, (_, trips))| trips.iter().map(move |&trip| (route, trip))),
+        );
+        self.prebuilt.patterns = patterns.into_iter().map(|(pattern, _)| pattern).collect();
+        self.build_stop_routes();
+
+        Ok(())
     }
 
-    fn cache_trips_for_routes(&self) -> TripsForRoutes {
-        let mut trips_for_routes = TripsForRoutes::default();
+    fn build_stop_routes(&mut self) {
+        let mut stop_routes = Vec::new();
+        for (route, pattern) in self.prebuilt.patterns.iter().enumerate() {
+            for (position, &stop) in self.prebuilt.route_stops[pattern.stops.clone()]
+                .iter()
+                .enumerate()
+            {
+                // stops visited twice by a route keep their first position
+                let visited =
+                    self.prebuilt.route_stops[pattern.stops.start..][..position].contains(&stop);
+                if !visited {
+                    stop_routes.push((stop, RouteStop { route, position }));
+                }
+            }
+        }
+        self.prebuilt.stop_routes = Adjacency::new(self.prebuilt.stops.len(), stop_routes);
+    }
+
+    fn build_stations(&mut self) {
+        let mut platforms = Vec::new();
+        self.prebuilt.stations = vec![None; self.prebuilt.stops.len()];
+
+        for (stop, stop_id) in self.prebuilt.stops.iter().enumerate() {
+            let gtfs_stop = &self.gtfs.stops[stop_id];
+            if gtfs_stop.location_type != LocationType::StopPoint {
+                continue;
+            }
 
-        for (trip_id, trip) in &self.gtfs.trips {
-            let route = self.route_idx(&trip.route_id).unwrap();
-            let trip_idx = self.trip_idx(trip_id).unwrap();
-            trips_for_routes.entry(route).or_default().push(trip_idx);
+            let station = gtfs_stop.parent_station.as_deref();
+            if let Some(station) = station.and_then(|id| self.prebuilt.stop_idx(id)) {
+                platforms.push((station, stop));
+                self.prebuilt.stations[stop] = Some(station);
+            }
+        }
+
+        self.prebuilt.platforms = Adjacency::new(self.prebuilt.stops.len(), platforms);
+    }
+
+    /// Footpaths of transfers.txt, along with the footpaths between the platforms of stations and
+    /// between nearby stops when generated. These walks are closed transitively, up to the time it
+    /// takes to walk the generation distance or between platforms. The min_transfer_time of
+    /// transfers.txt adds a buffer to the walk, so its footpaths are kept as they are.
+    fn build_footpaths(&mut self) {
+        let (mut footpaths, impossible) = self.feed_footpaths();
+
+        let mut walks = BTreeMap::new();
+        let mut max_walk = 0;
+        if let Some(transfer_time) = self.station_transfer_time {
+            max_walk = transfer_time;
+            for station in 0..self.prebuilt.stops.len() {
+                let platforms = self.prebuilt.platforms.get(station);
+                for &from in platforms {
+                    for &to in platforms {
+                        walks.insert((from, to), transfer_time);
+                    }
+                }
+            }
+        }
+        if let Some((max_distance, walking_speed)) = self.footpath_generation {
+            max_walk = max_walk.max((max_distance / walking_speed).ceil() as u32);
+            for (pair, duration) in self.nearby_stops(max_distance, walking_speed) {
+                walks.entry(pair).or_insert(duration);
+            }
+        }
+        walks.retain(|&(from, to), _| from != to && !impossible.contains(&(from, to)));
+
+        if walks.is_empty() {
+            self.set_footpaths(footpaths);
+            return;
+        }
+
+        let walks = Adjacency::new(
+            self.prebuilt.stops.len(),
+            walks
+                .into_iter()
+                .map(|((from, to), duration)| (from, (to, duration))),
+        );
+        let feed: BTreeSet<_> = footpaths
+            .iter()
+            .map(|&(from, (to, _))| (from, to))
+            .collect();
+
+        for from in 0..self.prebuilt.stops.len() {
+            for (to, duration) in shortest_walks(&walks, from, max_walk) {
+                let pair = (from, to);
+                if from != to && !feed.contains(&pair) && !impossible.contains(&pair) {
+                    footpaths.push((from, (to, Some(duration))));
+                }
+            }
+        }
+
+        self.set_footpaths(footpaths);
+    }
+
+    /// Pairs of stops within `max_distance` meters of each other, with the time to walk between
+    /// them at `walking_speed` meters per second. Stations, entrances and stops without
+    /// coordinates are left out.
+    fn nearby_stops(&self, max_distance: f64, walking_speed: f64) -> Vec<((Stop, Stop), u32)> {
+        let mut nearby_stops = Vec::new();
+
+        // stops by latitude, to only compare the stops less than `max_distance` north or south
+        let mut stops: Vec<_> = self
+            .prebuilt
+            .stops
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, id)| {
+                let stop = &self.gtfs.stops[id];
+                let boarding = stop.location_type == LocationType::StopPoint;
+                boarding.then_some((stop.latitude?, stop.longitude?, idx))
+            })
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let window = (max_distance / EARTH_RADIUS).to_degrees();
+
+        for (i, &(latitude, longitude, from)) in stops.iter().enumerate() {
+            let nearby = stops[i + 1..]
+                .iter()
+                .take_while(|&&(other_latitude, ..)| other_latitude - latitude <= window);
+
+            for &(other_latitude, other_longitude, to) in nearby {
+                let distance = haversine((latitude, longitude), (other_latitude, other_longitude));
+                if distance <= max_distance {
+                    let duration = (distance / walking_speed).ceil() as u32;
+                    nearby_stops.push(((from, to), duration));
+                    nearby_stops.push(((to, from), duration));
+                }
+            }
         }
 
-        // Sort each route's trips by first stop departure time
-        for trips in trips_for_routes.values_mut() {
-            trips.sort_by_key(|&trip_idx| {
-                let trip_id = &self.trips[trip_idx];
-                let trip = self.gtfs.get_trip(trip_id).unwrap();
-                trip.stop_times
-                    .first()
-                    .and_then(|st| st.departure_time)
-                    .unwrap_or(u32::MAX)
+        nearby_stops
+    }
+
+    /// Footpaths of transfers.txt, along with the pairs of stops between which transfers are
+    /// impossible
+    fn feed_footpaths(&self) -> (Vec<Footpath>, BTreeSet<(Stop, Stop)>) {
+        let mut footpaths = Vec::new();
+        let mut impossible = BTreeSet::new();
+
+        for (from, stop_id) in self.prebuilt.stops.iter().enumerate() {
+            for transfer in &self.gtfs.stops[stop_id].transfers {
+                let Some(to) = self.prebuilt.stop_idx(&transfer.to_stop_id) else {
+                    continue;
+                };
+                let min_transfer_time = match transfer.transfer_type {
+                    TransferType::Recommended | TransferType::MinTime => transfer.min_transfer_time,
+                    // treated as a minimum time of 0, the departing trip isn't held
+                    TransferType::Timed => Some(transfer.min_transfer_time.unwrap_or(0)),
+                    TransferType::Impossible => None,
+                    // in-seat transfers stay on the vehicle, there is nothing to walk
+                    TransferType::StayOnBoard | TransferType::MustAlight => continue,
+                };
+
+                // transfers between stations are made between all their platforms
+                for from in self.platforms(from) {
+                    for to in self.platforms(to) {
+                        // no footpath where transfers are impossible
+                        if transfer.transfer_type == TransferType::Impossible {
+                            impossible.insert((from, to));
+                        } else {
+                            footpaths.push((from, (to, min_transfer_time)));
+                        }
+                    }
+                }
+            }
+        }
+
+        (footpaths, impossible)
+    }
+
+    fn set_footpaths(&mut self, footpaths: Vec<Footpath>) {
+        self.prebuilt.footpaths_to = Adjacency::new(
+            self.prebuilt.stops.len(),
+            footpaths.iter().map(|&(from, (to, _))| (to, from)),
+        );
+        self.prebuilt.footpaths = Adjacency::new(self.prebuilt.stops.len(), footpaths);
+    }
+
+    // first known time of the trip, the first stop being a timepoint in valid feeds
+    fn gtfs_first_departure(&self, trip: usize) -> Option<u32> {
+        self.gtfs.trips[&self.prebuilt.trips[trip]]
+            .stop_times
+            .iter()
+            .find_map(|st| st.departure_time.or(st.arrival_time))
+    }
+
+    /// Whether `trip` arrives or departs earlier than `other` at any of their stops, both trips
+    /// calling at the same stops. Stops without a time in either trip aren't compared.
+    fn overtakes(&self, trip: usize, other: usize) -> bool {
+        let trip = &self.gtfs.trips[&self.prebuilt.trips[trip]];
+        let other = &self.gtfs.trips[&self.prebuilt.trips[other]];
+
+        // the runs of frequency-based trips are spread over the day, they get their own patterns
+        if !trip.frequencies.is_empty() || !other.frequencies.is_empty() {
+            return true;
+        }
+
+        trip.stop_times
+            .iter()
+            .zip(&other.stop_times)
+            .any(|(st, other)| {
+                let earlier = |time: Option<u32>, other: Option<u32>| {
+                    time.zip(other).is_some_and(|(time, other)| time < other)
+                };
+
+                earlier(st.arrival_time, other.arrival_time)
+                    || earlier(st.departure_time, other.departure_time)
+            })
+    }
+
+    /// Runs of every route on the service date, without the cancelled ones
+    fn build_runs(&mut self) {
+        let mut runs = Vec::new();
+        let mut runs_in_order = Vec::new();
+        let previous_date = self.service_date().and_then(|date| date.pred_opt());
+
+        for route in 0..self.prebuilt.patterns.len() {
+            let first = runs.len();
+
+            for &trip_idx in self.prebuilt.pattern_trips.get(route) {
+                // realtime trips run once, on the service date
+                if trip_idx >= self.prebuilt.trips.len() {
+                    let run = TripRun {
+                        trip: trip_idx,
+                        previous_day: false,
+                        start: None,
+                    };
+                    runs.push((route, run));
+                    continue;
+                }
+
+                let trip = &self.gtfs.trips[&self.prebuilt.trips[trip_idx]];
+
+                let runs_today = self
+                    .service_date()
+                    .is_none_or(|date| self.is_service_active(&trip.service_id, date));
+                let ran_yesterday = previous_date
+                    .is_some_and(|date| self.is_service_active(&trip.service_id, date));
+
+                for start in self.starts(trip_idx) {
+                    let run = TripRun {
+                        trip: trip_idx,
+                        previous_day: false,
+                        start,
+                    };
+
+                    if runs_today && !self.cancelled_runs.contains(&run) {
+                        runs.push((route, run));
+                    }
+
+                    let last = self.prebuilt.patterns[route].stops.len() - 1;
+                    let runs_past_midnight = self
+                        .prebuilt
+                        .stop_time(run, last)
+                        .is_some_and(|(arrival, _)| arrival >= DAY);
+                    let run = TripRun {
+                        previous_day: true,
+                        ..run
+                    };
+                    if runs_past_midnight && ran_yesterday && !self.cancelled_runs.contains(&run) {
+                        runs.push((route, run));
+                    }
+                }
+            }
+
+            // Sort by first stop departure time, runs of the previous day first
+            runs[first..].sort_by_key(|&(_, run)| {
+                self.prebuilt.shift(run) + self.prebuilt.template(run)[0].1 as i64
             });
+            // runs of the previous day and of frequencies can still pass each other further on
+            let route_runs: Vec<_> = runs[first..].iter().map(|&(_, run)| run).collect();
+            runs_in_order.push(self.prebuilt.in_order(route, &route_runs));
         }
 
-        trips_for_routes
+        self.prebuilt.runs = Adjacency::new(self.prebuilt.patterns.len(), runs);
+        self.prebuilt.runs_in_order = runs_in_order;
     }
 
-    fn stop_idx(&self, id: &str) -> Option<usize> {
-        self.stops.binary_search_by(|item| (*item).cmp(id)).ok()
+    /// Departures from the first stop of the runs of a trip: one per headway for frequency-based
+    /// trips, or a single `None` run at the times of `stop_times.txt`.
+    ///
+    /// The vehicles of trips without `exact_times` don't follow a schedule, so their runs are
+    /// delayed by a headway: the longest a rider can wait for the next vehicle.
+    fn starts(&self, trip: usize) -> Vec<Option<u32>> {
+        let frequencies = &self.gtfs.trips[&self.prebuilt.trips[trip]].frequencies;
+
+        if frequencies.is_empty() {
+            return vec![None];
+        }
+
+        frequencies
+            .iter()
+            .filter(|frequency| frequency.headway_secs > 0)
+            .flat_map(|frequency| {
+                let wait = match frequency.exact_times {
+                    Some(ExactTimes::ScheduleBased) => 0,
+                    _ => frequency.headway_secs,
+                };
+
+                (frequency.start_time..frequency.end_time)
+                    .step_by(frequency.headway_secs as usize)
+                    .map(move |start| Some(start + wait))
+            })
+            .collect()
     }
-    fn trip_idx(&self, id: &str) -> Option<usize> {
-        self.trips.binary_search_by(|item| (*item).cmp(id)).ok()
+
+    /// The timetable queries run on
+    pub fn prebuilt(&self) -> &PrebuiltTimetable {
+        &self.prebuilt
     }
-    fn route_idx(&self, id: &str) -> Option<usize> {
-        self.routes.binary_search_by(|item| (*item).cmp(id)).ok()
+
+    /// The timetable queries run on, letting go of the feed
+    pub fn into_prebuilt(self) -> PrebuiltTimetable {
+        self.prebuilt
     }
 
+    /// See [`PrebuiltTimetable::resolve_stop`]
     pub fn resolve_stop(&self, idx: usize) -> Option<&str> {
-        self.stops.get(idx).copied()
+        self.prebuilt.resolve_stop(idx)
     }
 
+    /// See [`PrebuiltTimetable::resolve_route`]
     pub fn resolve_route(&self, idx: usize) -> Option<&str> {
-        self.routes.get(idx).copied()
+        self.prebuilt.resolve_route(idx)
+    }
+
+    /// See [`PrebuiltTimetable::resolve_trip`]
+    pub fn resolve_trip(&self, idx: usize) -> Option<&str> {
+        self.prebuilt.resolve_trip(idx)
     }
 
+    /// See [`PrebuiltTimetable::lookup_stop`]
     pub fn lookup_stop(&self, id: &str) -> Option<usize> {
-        self.stop_idx(id)
+        self.prebuilt.lookup_stop(id)
+    }
+
+    /// See [`PrebuiltTimetable::platforms`]
+    pub fn platforms(&self, stop: usize) -> impl Iterator<Item = usize> + '_ {
+        self.prebuilt.platforms(stop)
+    }
+
+    /// See [`PrebuiltTimetable::station`]
+    pub fn station(&self, stop: usize) -> usize {
+        self.prebuilt.station(stop)
+    }
+
+    /// See [`PrebuiltTimetable::station_raptor`]
+    pub fn station_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+        pt: usize,
+    ) -> Vec<JourneyFor<Self>> {
+        self.prebuilt.station_raptor(transfers, tau, ps, pt)
+    }
+
+    /// See [`PrebuiltTimetable::try_station_raptor`]
+    pub fn try_station_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+        pt: usize,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.prebuilt.try_station_raptor(transfers, tau, ps, pt)
+    }
+
+    /// See [`PrebuiltTimetable::station_journey`]
+    pub fn station_journey(&self, journey: &JourneyFor<Self>) -> JourneyFor<Self> {
+        self.prebuilt.station_journey(journey)
+    }
+
+    /// See [`PrebuiltTimetable::earliest_arrivals_dense`]
+    pub fn earliest_arrivals_dense(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+    ) -> Vec<Option<(crate::Tau, crate::K)>> {
+        self.prebuilt.earliest_arrivals_dense(transfers, tau, ps)
+    }
+
+    /// See [`PrebuiltTimetable::try_earliest_arrivals_dense`]
+    pub fn try_earliest_arrivals_dense(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+    ) -> Result<Vec<Option<(crate::Tau, crate::K)>>, Error> {
+        self.prebuilt
+            .try_earliest_arrivals_dense(transfers, tau, ps)
+    }
+}
+
+impl GtfsTimetable<'static> {
+    /// Panics where [`GtfsTimetable::try_new_owned`] returns an error
+    pub fn new_owned(gtfs: impl Into<Arc<Gtfs>>) -> Self {
+        Self::try_new_owned(gtfs).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// [`GtfsTimetable::try_new`] taking the feed, or sharing it with other timetables. The
+    /// timetable then borrows nothing: it can be kept in an `Arc` shared by threads or async tasks,
+    /// and replaced at runtime.
+    pub fn try_new_owned(gtfs: impl Into<Arc<Gtfs>>) -> Result<Self, Error> {
+        Self::build(Feed::Shared(gtfs.into()))
     }
 }
 
@@ -133,14 +1096,10 @@ impl Timetable for GtfsTimetable<'_> {
 
     type Route = usize;
 
-    type Trip = usize;
+    type Trip = TripRun;
 
-    fn get_routes_serving_stop(&self, stop: Self::Stop) -> Vec<Self::Route> {
-        self.routes_for_stops
-            .get_or_init(|| self.cache_routes_for_stops())
-            .get(&stop)
-            .map(|sv| sv.to_vec())
-            .unwrap_or_default()
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> + '_ {
+        self.prebuilt.get_routes_serving_stop(stop)
     }
 
     fn get_earlier_stop(
@@ -149,123 +1108,1076 @@ impl Timetable for GtfsTimetable<'_> {
         left: Self::Stop,
         right: Self::Stop,
     ) -> Self::Stop {
-        let stops = self
-            .stops_for_routes
-            .get_or_init(|| self.cache_stops_for_routes())
-            .get(&route)
-            .expect("route should exist");
+        self.prebuilt.get_earlier_stop(route, left, right)
+    }
 
-        let left_pos = stops.iter().position(|&s| s == left);
-        let right_pos = stops.iter().position(|&s| s == right);
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.prebuilt.get_stops_after(route, stop)
+    }
 
-        match (left_pos, right_pos) {
-            (Some(l), Some(r)) if l <= r => left,
-            (Some(_), Some(_)) => right,
-            _ => panic!("both stops should exist on route"),
-        }
+    fn get_earliest_trip(
+        &self,
+        route: Self::Route,
+        at: crate::Tau,
+        stop: Self::Stop,
+    ) -> Option<Self::Trip> {
+        self.prebuilt.get_earliest_trip(route, at, stop)
     }
 
-    fn get_stops_after(&self, route: Self::Route, stop: Self::Stop) -> Vec<Self::Stop> {
-        let stops = self
-            .stops_for_routes
-            .get_or_init(|| self.cache_stops_for_routes())
-            .get(&route)
-            .expect("route should exist");
+    fn get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> crate::Tau {
+        self.prebuilt.get_arrival_time(trip, stop)
+    }
 
-        let pos = stops
-            .iter()
-            .position(|&s| s == stop)
-            .expect("stop should exist on route");
+    fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> crate::Tau {
+        self.prebuilt.get_departure_time(trip, stop)
+    }
 
-        stops[pos..].to_vec()
+    fn get_footpaths_from(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.prebuilt.get_footpaths_from(stop)
     }
 
-    fn get_earliest_trip(
+    fn get_transfer_time(&self, from: Self::Stop, to: Self::Stop) -> crate::Tau {
+        self.prebuilt.get_transfer_time(from, to)
+    }
+
+    fn get_min_change_time(&self, stop: Self::Stop) -> crate::Tau {
+        self.prebuilt.get_min_change_time(stop)
+    }
+
+    fn get_trip_transfer_time(
+        &self,
+        from: (Self::Route, Self::Trip, Self::Stop),
+        to: (Self::Route, Self::Trip, Self::Stop),
+    ) -> Option<crate::Tau> {
+        self.prebuilt.get_trip_transfer_time(from, to)
+    }
+
+    fn check_stop(&self, stop: Self::Stop) -> Result<(), Error> {
+        self.prebuilt.check_stop(stop)
+    }
+
+    fn try_get_earlier_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Result<Self::Stop, Error> {
+        self.prebuilt.try_get_earlier_stop(route, left, right)
+    }
+
+    fn try_get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        self.prebuilt.try_get_stops_after(route, stop)
+    }
+
+    fn try_get_arrival_time(
+        &self,
+        trip: Self::Trip,
+        stop: Self::Stop,
+    ) -> Result<crate::Tau, Error> {
+        self.prebuilt.try_get_arrival_time(trip, stop)
+    }
+
+    fn try_get_departure_time(
+        &self,
+        trip: Self::Trip,
+        stop: Self::Stop,
+    ) -> Result<crate::Tau, Error> {
+        self.prebuilt.try_get_departure_time(trip, stop)
+    }
+}
+
+impl ReverseTimetable for GtfsTimetable<'_> {
+    fn get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.prebuilt.get_stops_before(route, stop)
+    }
+
+    fn try_get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        self.prebuilt.try_get_stops_before(route, stop)
+    }
+
+    fn get_latest_trip(
         &self,
         route: Self::Route,
         at: crate::Tau,
         stop: Self::Stop,
     ) -> Option<Self::Trip> {
-        let trips = self
-            .trips_for_routes
-            .get_or_init(|| self.cache_trips_for_routes())
-            .get(&route)?;
+        self.prebuilt.get_latest_trip(route, at, stop)
+    }
 
-        let stop_id = *self.stops.get(stop)?;
+    fn get_footpaths_to(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.prebuilt.get_footpaths_to(stop)
+    }
+}
 
-        let departure_at_stop = |trip_idx: usize| -> Option<crate::Tau> {
-            let trip_id = self.trips[trip_idx];
-            let trip = self.gtfs.get_trip(trip_id).unwrap();
-            trip.stop_times
-                .iter()
-                .find(|st| st.stop.id == stop_id)
-                .and_then(|st| st.departure_time)
-                .map(|t| t as crate::Tau)
-        };
+impl IndexedTimetable for GtfsTimetable<'_> {
+    fn stop_count(&self) -> usize {
+        self.prebuilt.stop_count()
+    }
 
-        // Binary search: find partition point where departure >= at
-        let idx = trips.partition_point(|&trip_idx| {
-            departure_at_stop(trip_idx)
-                .map(|dep| dep < at)
-                .unwrap_or(true) // trips not serving this stop sort "before"
-        });
+    fn route_count(&self) -> usize {
+        self.prebuilt.route_count()
+    }
+}
 
-        // Scan forward to find first trip actually serving this stop
-        trips[idx..]
-            .iter()
-            .find(|&&trip_idx| departure_at_stop(trip_idx).is_some())
-            .copied()
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+    use std::fs;
+
+    use super::*;
+    use crate::workspace::RaptorWorkspace;
+    use crate::{Leg, RaptorQuery};
+
+    type Trip<'a> = (&'a str, &'a str, &'a [(&'a str, &'a str)]);
+
+    /// A feed of `trips`, given as (route, trip, [(stop, time)]) and running every day, along
+    /// with the `extra` files. Blank times are non-timepoints.
+    pub(super) fn feed(name: &str, trips: &[Trip], extra: &[(&str, &str)]) -> Gtfs {
+        let dir = std::env::temp_dir().join(format!("raptor-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut stops = BTreeSet::new();
+        let mut routes = BTreeSet::new();
+        let mut trips_txt = String::from("route_id,service_id,trip_id\n");
+        let mut stop_times =
+            String::from("trip_id,arrival_time,departure_time,stop_id,stop_sequence\n");
+        for &(route, trip, calls) in trips {
+            routes.insert(route);
+            writeln!(trips_txt, "{route},S,{trip}").unwrap();
+            for (sequence, &(stop, time)) in calls.iter().enumerate() {
+                stops.insert(stop);
+                let time = if time.is_empty() {
+                    String::new()
+                } else {
+                    format!("{time}:00")
+                };
+                writeln!(stop_times, "{trip},{time},{time},{stop},{sequence}").unwrap();
+            }
+        }
+
+        let mut stops_txt = String::from("stop_id,stop_name,stop_lat,stop_lon\n");
+        for (idx, stop) in stops.iter().enumerate() {
+            writeln!(
+                stops_txt,
+                "{stop},{stop},{},2.35",
+                48.85 + idx as f64 * 0.01
+            )
+            .unwrap();
+        }
+        let mut routes_txt = String::from("route_id,agency_id,route_short_name,route_type\n");
+        for route in routes {
+            writeln!(routes_txt, "{route},AG,{route},3").unwrap();
+        }
+
+        let files = [
+            (
+                "agency.txt",
+                "agency_id,agency_name,agency_url,agency_timezone\nAG,Agency,http://example.com,Europe/Paris\n",
+            ),
+            (
+                "calendar.txt",
+                "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\nS,1,1,1,1,1,1,1,20260101,20261231\n",
+            ),
+            ("stops.txt", &stops_txt),
+            ("routes.txt", &routes_txt),
+            ("trips.txt", &trips_txt),
+            ("stop_times.txt", &stop_times),
+        ];
+        for (file, contents) in files.into_iter().chain(extra.iter().copied()) {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+
+        let gtfs = Gtfs::new(dir.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        gtfs
     }
 
-    fn get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> crate::Tau {
-        let trip_id = self.trips[trip];
-        let stop_id = self.stops[stop];
-        let trip = self.gtfs.get_trip(trip_id).unwrap();
+    fn stop(timetable: &GtfsTimetable, id: &str) -> usize {
+        timetable.lookup_stop(id).unwrap()
+    }
 
-        trip.stop_times
+    fn time(time: &str) -> crate::Tau {
+        let (hours, minutes) = time.split_once(':').unwrap();
+        hours.parse::<crate::Tau>().unwrap() * 3600 + minutes.parse::<crate::Tau>().unwrap() * 60
+    }
+
+    /// Ids of the trips ridden by `journey`
+    fn trips<'a>(
+        timetable: &'a GtfsTimetable,
+        journey: &JourneyFor<GtfsTimetable>,
+    ) -> Vec<&'a str> {
+        journey
+            .legs
             .iter()
-            .find(|st| st.stop.id == stop_id)
-            .and_then(|st| st.arrival_time)
-            .expect("valid inputs") as crate::Tau
+            .filter_map(|leg| match leg {
+                Leg::Transit { trip, .. } => timetable.resolve_trip(trip.trip),
+                _ => None,
+            })
+            .collect()
     }
 
-    fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> crate::Tau {
-        let trip_id = self.trips[trip];
-        let stop_id = self.stops[stop];
-        let trip = self.gtfs.get_trip(trip_id).unwrap();
+    // timetables are shared between threads, e.g. by the parallel queries
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    const _: fn() = || {
+        assert_send_sync::<GtfsTimetable<'static>>();
+        assert_send_sync::<PrebuiltTimetable>();
+    };
 
-        trip.stop_times
-            .iter()
-            .find(|st| st.stop.id == stop_id)
-            .and_then(|st| st.departure_time)
-            .expect("valid inputs") as crate::Tau
+    #[test]
+    fn arrive_by_agrees_with_depart_at_on_min_change_time() {
+        let gtfs = feed(
+            "min_change_time",
+            &[
+                ("R1", "a1", &[("A", "08:00"), ("B", "08:20")]),
+                ("R2", "b1", &[("B", "08:25"), ("C", "08:45")]),
+                ("R2", "b2", &[("B", "08:40"), ("C", "09:00")]),
+            ],
+            &[],
+        );
+        let timetable = GtfsTimetable::new(&gtfs).with_min_change_time(600);
+        let (a, c) = (stop(&timetable, "A"), stop(&timetable, "C"));
+
+        let depart_at = timetable.raptor(3, time("07:00"), a, c);
+        assert_eq!(depart_at.len(), 1);
+        assert_eq!(depart_at[0].arrival, time("09:00"));
+        assert_eq!(trips(&timetable, &depart_at[0]), ["a1", "b2"]);
+
+        // arriving by 08:45 leaves 5 minutes to change at B
+        assert!(
+            timetable
+                .raptor_arrive_by(3, time("08:45"), a, c)
+                .is_empty()
+        );
+
+        let arrive_by = timetable.raptor_arrive_by(3, time("09:00"), a, c);
+        assert_eq!(arrive_by.len(), 1);
+        assert_eq!(arrive_by[0].departure, time("08:00"));
+        assert_eq!(trips(&timetable, &arrive_by[0]), ["a1", "b2"]);
     }
 
-    fn get_footpaths_from(&self, stop: Self::Stop) -> Vec<Self::Stop> {
-        let stop_id = self.stops[stop];
+    #[test]
+    fn arrive_by_walks_between_trips() {
+        let gtfs = feed(
+            "arrive_by_walk",
+            &[
+                ("R1", "a1", &[("A", "08:00"), ("B", "08:20")]),
+                ("R1", "a2", &[("A", "08:05"), ("B", "08:26")]),
+                ("R2", "b1", &[("C", "08:30"), ("D", "08:50")]),
+            ],
+            &[(
+                "transfers.txt",
+                "from_stop_id,to_stop_id,transfer_type,min_transfer_time\nB,C,2,300\n",
+            )],
+        );
+        let timetable = GtfsTimetable::new(&gtfs);
+        let [a, b, c, d] = ["A", "B", "C", "D"].map(|id| stop(&timetable, id));
 
-        self.gtfs
-            .get_stop(stop_id)
-            .unwrap()
-            .transfers
-            .iter()
-            .filter_map(|t| self.stop_idx(&t.to_stop_id))
-            .collect()
+        // a2 leaves 4 minutes to walk to C
+        let journeys = timetable.raptor_arrive_by(3, time("09:00"), a, d);
+        assert_eq!(journeys.len(), 1);
+        let journey = &journeys[0];
+        assert_eq!(
+            (journey.departure, journey.arrival),
+            (time("08:00"), time("08:50"))
+        );
+        assert_eq!(trips(&timetable, journey), ["a1", "b1"]);
+        assert_eq!(
+            journey.legs[1],
+            Leg::Walk {
+                from: b,
+                to: c,
+                duration: 300
+            }
+        );
     }
 
-    // TODO: handle TransferType to distinguish between timed transfers and walking
-    fn get_transfer_time(&self, from: Self::Stop, to: Self::Stop) -> crate::Tau {
-        let from_stop_id = self.stops[from];
-        let to_stop_id = self.stops[to];
+    #[test]
+    fn footpaths_are_closed_up_to_the_generation_distance() {
+        // stops 1.1 km apart, each one within walking distance of the next only
+        let gtfs = feed(
+            "footpath_closure",
+            &[(
+                "R1",
+                "a1",
+                &[
+                    ("A", "08:00"),
+                    ("B", "08:10"),
+                    ("C", "08:20"),
+                    ("D", "08:30"),
+                ],
+            )],
+            &[],
+        );
+        let mut timetable = GtfsTimetable::new(&gtfs)
+            .with_generated_footpaths(1200.0, 1.0)
+            .unwrap();
+        let [a, b, c, d] = ["A", "B", "C", "D"].map(|id| stop(&timetable, id));
+
+        assert_eq!(timetable.get_footpaths_from(a).collect::<Vec<_>>(), [b]);
+        assert_eq!(timetable.get_footpaths_from(b).collect::<Vec<_>>(), [a, c]);
+        assert_eq!(timetable.get_footpaths_from(d).collect::<Vec<_>>(), [c]);
+
+        // invalid options are rejected, keeping the footpaths
+        for (max_distance, walking_speed) in [
+            (-1.0, 1.0),
+            (f64::NAN, 1.0),
+            (1200.0, 0.0),
+            (1200.0, f64::NAN),
+        ] {
+            assert!(matches!(
+                timetable.generate_footpaths(max_distance, walking_speed),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+        assert_eq!(timetable.get_footpaths_from(b).collect::<Vec<_>>(), [a, c]);
+    }
+
+    #[test]
+    fn calendar_dates_add_and_remove_service() {
+        let gtfs = feed(
+            "calendar_dates",
+            &[("R1", "a1", &[("A", "08:00"), ("B", "08:20")])],
+            &[(
+                "calendar_dates.txt",
+                "service_id,date,exception_type\nS,20261014,2\nS,20270105,1\n",
+            )],
+        );
+        let runs = |year, month, day| {
+            let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+            let timetable = GtfsTimetable::new(&gtfs).with_service_date(date);
+            let (a, b) = (stop(&timetable, "A"), stop(&timetable, "B"));
+            !timetable.raptor(3, time("07:00"), a, b).is_empty()
+        };
 
-        self.gtfs
-            .get_stop(from_stop_id)
-            .unwrap()
-            .transfers
+        assert!(runs(2026, 10, 13));
+        assert!(!runs(2026, 10, 14));
+        // past the end of calendar.txt
+        assert!(runs(2027, 1, 5));
+        assert!(!runs(2027, 1, 6));
+    }
+
+    #[test]
+    fn overnight_runs_carry_into_the_next_day() {
+        let gtfs = feed(
+            "overnight",
+            &[(
+                "R1",
+                "n1",
+                &[("A", "23:50"), ("B", "24:10"), ("C", "24:30")],
+            )],
+            &[],
+        );
+        let date = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let timetable = GtfsTimetable::new(&gtfs).with_service_date(date);
+        let [a, b, c] = ["A", "B", "C"].map(|id| stop(&timetable, id));
+
+        let journeys = timetable.raptor(3, time("00:00"), b, c);
+        assert_eq!(journeys[0].arrival, time("00:30"));
+        let journeys = timetable.raptor(3, time("00:00"), a, c);
+        assert_eq!(journeys[0].arrival, time("24:30"));
+
+        // the run of the previous day left A before midnight
+        let route = timetable.get_routes_serving_stop(b).next().unwrap();
+        let run = timetable.get_earliest_trip(route, 0, b).unwrap();
+        assert_eq!(timetable.try_get_departure_time(run, b), Ok(time("00:10")));
+        assert!(matches!(
+            timetable.try_get_departure_time(run, a),
+            Err(Error::TripNotAtStop { .. })
+        ));
+
+        let journeys = timetable.raptor_arrive_by(3, time("00:40"), b, c);
+        assert_eq!(journeys[0].departure, time("00:10"));
+        assert!(
+            timetable
+                .raptor_arrive_by(3, time("00:40"), a, c)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn routes_split_by_stops_and_overtaking_trips() {
+        let gtfs = feed(
+            "patterns",
+            &[
+                (
+                    "R1",
+                    "s1",
+                    &[("A", "08:00"), ("B", "08:10"), ("C", "08:20")],
+                ),
+                ("R1", "s2", &[("A", "08:30"), ("C", "08:45")]),
+                (
+                    "R1",
+                    "s3",
+                    &[("A", "08:05"), ("B", "08:30"), ("C", "08:50")],
+                ),
+                // overtakes s3 at B
+                (
+                    "R1",
+                    "s4",
+                    &[("A", "08:10"), ("B", "08:15"), ("C", "08:25")],
+                ),
+            ],
+            &[],
+        );
+        let timetable = GtfsTimetable::new(&gtfs);
+        let [a, b, c] = ["A", "B", "C"].map(|id| stop(&timetable, id));
+
+        let routes = timetable.get_routes_serving_stop(a).collect::<Vec<_>>();
+        assert_eq!(routes.len(), 3);
+        assert_eq!(timetable.get_routes_serving_stop(b).count(), 2);
+        assert!(
+            routes
+                .iter()
+                .all(|&route| timetable.resolve_route(route) == Some("R1"))
+        );
+
+        // earliest trip of every pattern
+        let mut earliest = routes
             .iter()
-            .find(|t| t.to_stop_id == to_stop_id)
-            .and_then(|t| t.min_transfer_time)
-            .map(|t| t as crate::Tau)
-            .unwrap_or(300) // default 5 minutes
+            .map(|&route| {
+                let trip = timetable
+                    .get_earliest_trip(route, time("08:01"), a)
+                    .unwrap();
+                timetable.resolve_trip(trip.trip).unwrap()
+            })
+            .collect::<Vec<_>>();
+        earliest.sort();
+        assert_eq!(earliest, ["s2", "s3", "s4"]);
+
+        let journeys = timetable.raptor(3, time("08:01"), a, c);
+        assert_eq!(trips(&timetable, &journeys[0]), ["s4"]);
+        assert_eq!(journeys[0].arrival, time("08:25"));
+    }
+
+    #[test]
+    fn frequencies_expand_into_runs() {
+        let gtfs = feed(
+            "frequencies",
+            &[
+                ("R1", "e1", &[("A", "08:00"), ("B", "08:10")]),
+                ("R2", "f1", &[("C", "10:00"), ("D", "10:15")]),
+            ],
+            &[(
+                "frequencies.txt",
+                "trip_id,start_time,end_time,headway_secs,exact_times\ne1,08:00:00,09:00:00,1200,1\nf1,08:00:00,09:00:00,1200,0\n",
+            )],
+        );
+        let timetable = GtfsTimetable::new(&gtfs);
+        let [a, b, c, d] = ["A", "B", "C", "D"].map(|id| stop(&timetable, id));
+        let runs = |from, to| {
+            timetable
+                .range_raptor(3, 0..=time("24:00"), from, to)
+                .iter()
+                .map(|journey| (journey.departure, journey.arrival))
+                .collect::<Vec<_>>()
+        };
+
+        // every headway from the start time, the end time left out
+        assert_eq!(
+            runs(a, b),
+            [
+                (time("08:00"), time("08:10")),
+                (time("08:20"), time("08:30")),
+                (time("08:40"), time("08:50")),
+            ]
+        );
+        // without exact times, a headway later
+        assert_eq!(
+            runs(c, d),
+            [
+                (time("08:20"), time("08:35")),
+                (time("08:40"), time("08:55")),
+                (time("09:00"), time("09:15")),
+            ]
+        );
+    }
+
+    fn two_trips() -> Gtfs {
+        feed(
+            "errors",
+            &[
+                ("R1", "a1", &[("A", "08:00"), ("B", "08:20")]),
+                ("R2", "b1", &[("B", "08:30"), ("C", "08:40")]),
+            ],
+            &[],
+        )
+    }
+
+    #[test]
+    fn unknown_stops_are_errors() {
+        let gtfs = two_trips();
+        let timetable = GtfsTimetable::new(&gtfs);
+        let a = stop(&timetable, "A");
+        let unknown = Error::UnknownStop("99".to_string());
+
+        assert_eq!(timetable.try_raptor(3, 0, a, 99).unwrap_err(), unknown);
+        assert_eq!(timetable.try_raptor(3, 0, 99, a).unwrap_err(), unknown);
+        let query = RaptorQuery::new(99, a, 0);
+        assert_eq!(timetable.try_query(&query).unwrap_err(), unknown);
+        assert_eq!(
+            timetable.try_raptor_arrive_by(3, 0, a, 99).unwrap_err(),
+            unknown
+        );
+    }
+
+    #[test]
+    fn trips_not_at_a_stop_are_errors() {
+        let gtfs = two_trips();
+        let timetable = GtfsTimetable::new(&gtfs);
+        let [a, c] = ["A", "C"].map(|id| stop(&timetable, id));
+        let route = timetable.get_routes_serving_stop(a).next().unwrap();
+        let trip = timetable.get_earliest_trip(route, 0, a).unwrap();
+
+        assert_eq!(
+            timetable.try_get_arrival_time(trip, c),
+            Err(Error::TripNotAtStop {
+                trip: "a1".to_string(),
+                stop: "C".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown stop 99")]
+    fn raptor_panics_on_unknown_stops() {
+        let gtfs = two_trips();
+        let timetable = GtfsTimetable::new(&gtfs);
+        timetable.raptor(3, 0, 0, 99);
+    }
+
+    #[test]
+    #[should_panic(expected = "trip a1 doesn't call at stop C")]
+    fn arrival_time_panics_on_trips_not_at_a_stop() {
+        let gtfs = two_trips();
+        let timetable = GtfsTimetable::new(&gtfs);
+        let [a, c] = ["A", "C"].map(|id| stop(&timetable, id));
+        let route = timetable.get_routes_serving_stop(a).next().unwrap();
+        let trip = timetable.get_earliest_trip(route, 0, a).unwrap();
+        timetable.get_arrival_time(trip, c);
+    }
+
+    #[test]
+    fn reused_workspace_finds_the_same_journeys() {
+        let gtfs = feed(
+            "workspace",
+            &[
+                (
+                    "R1",
+                    "a1",
+                    &[("A", "08:00"), ("B", "08:10"), ("C", "08:20")],
+                ),
+                (
+                    "R1",
+                    "a2",
+                    &[("A", "08:30"), ("B", "08:40"), ("C", "08:50")],
+                ),
+                ("R2", "b1", &[("B", "08:15"), ("D", "08:30")]),
+                ("R2", "b2", &[("C", "08:25"), ("D", "08:45")]),
+                ("R3", "c1", &[("D", "08:35"), ("A", "08:50")]),
+            ],
+            &[],
+        );
+        let timetable = GtfsTimetable::new(&gtfs).with_min_change_time(120);
+        let mut workspace = RaptorWorkspace::for_timetable(&timetable);
+        let stops = ["A", "B", "C", "D"].map(|id| stop(&timetable, id));
+
+        // every query in the same workspace, the labels of the previous ones left behind
+        for tau in ["07:00", "08:05", "08:20", "08:45"].map(time) {
+            for ps in stops {
+                for pt in stops {
+                    assert_eq!(
+                        format!("{:?}", timetable.raptor_in(&mut workspace, 3, tau, ps, pt)),
+                        format!("{:?}", timetable.raptor(3, tau, ps, pt)),
+                    );
+
+                    let query = RaptorQuery::new(ps, pt, tau).with_max_arrival(time("08:45"));
+                    assert_eq!(
+                        format!("{:?}", timetable.query_in(&mut workspace, &query)),
+                        format!("{:?}", timetable.query(&query)),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn most_specific_transfer_rule_applies() {
+        let gtfs = feed(
+            "transfer_rules",
+            &[
+                ("R1", "a1", &[("A", "08:00"), ("B", "08:20")]),
+                ("R3", "d1", &[("D", "08:00"), ("B", "08:20")]),
+                ("R2", "b1", &[("B", "08:25"), ("C", "08:45")]),
+                ("R2", "b2", &[("B", "08:40"), ("C", "09:00")]),
+                ("R2", "b3", &[("B", "08:55"), ("C", "09:15")]),
+            ],
+            &[],
+        );
+        let stop_rule = TransferRule {
+            from_stop_id: Some("B".to_string()),
+            to_stop_id: Some("B".to_string()),
+            from_route_id: None,
+            to_route_id: None,
+            from_trip_id: None,
+            to_trip_id: None,
+            transfer_type: TransferType::MinTime,
+            min_transfer_time: Some(1800),
+        };
+        let route_rule = TransferRule {
+            from_route_id: Some("R1".to_string()),
+            to_route_id: Some("R2".to_string()),
+            min_transfer_time: Some(900),
+            ..stop_rule.clone()
+        };
+        let trip_rule = TransferRule {
+            from_trip_id: Some("a1".to_string()),
+            to_trip_id: Some("b1".to_string()),
+            transfer_type: TransferType::Timed,
+            min_transfer_time: None,
+            ..stop_rule.clone()
+        };
+
+        // trips taken to C from A and from D
+        let changes = |rules: &[TransferRule]| {
+            let timetable = GtfsTimetable::new(&gtfs)
+                .with_transfer_rules(rules)
+                .unwrap();
+            let [a, c, d] = ["A", "C", "D"].map(|id| stop(&timetable, id));
+            [a, d].map(|from| {
+                let journeys = timetable.raptor(3, time("07:00"), from, c);
+                trips(&timetable, journeys.last().unwrap())[1].to_string()
+            })
+        };
+
+        assert_eq!(changes(std::slice::from_ref(&stop_rule)), ["b3", "b3"]);
+        assert_eq!(
+            changes(&[stop_rule.clone(), route_rule.clone()]),
+            ["b2", "b3"]
+        );
+        // whatever the order of the rules
+        assert_eq!(changes(&[trip_rule, route_rule, stop_rule]), ["b1", "b3"]);
+    }
+
+    #[test]
+    fn in_seat_transfers_of_transfers_txt_are_not_footpaths() {
+        let gtfs = feed(
+            "in_seat_transfers",
+            &[
+                ("R1", "a1", &[("A", "08:00"), ("B", "08:20")]),
+                ("R2", "b1", &[("C", "08:25"), ("D", "08:45")]),
+                ("R2", "b2", &[("B", "08:21"), ("D", "08:50")]),
+            ],
+            &[(
+                "transfers.txt",
+                "from_stop_id,to_stop_id,transfer_type,min_transfer_time\nB,C,4,\nB,D,5,\nB,B,4,\nC,D,2,120\n",
+            )],
+        );
+        let timetable = GtfsTimetable::new(&gtfs).with_min_change_time(300);
+        let [b, c, d] = ["B", "C", "D"].map(|id| stop(&timetable, id));
+
+        assert_eq!(timetable.get_footpaths_from(b).count(), 0);
+        assert_eq!(timetable.get_footpaths_from(c).collect::<Vec<_>>(), [d]);
+        // the stop keeps its minimum change time
+        let a = stop(&timetable, "A");
+        assert!(timetable.raptor(3, time("07:00"), a, d).is_empty());
+    }
+
+    #[test]
+    fn stations_are_searched_through_their_platforms() {
+        let gtfs = feed(
+            "stations",
+            &[("R1", "a1", &[("A1", "08:00"), ("B1", "08:20")])],
+            &[(
+                "stops.txt",
+                "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+                 SA,Station A,48.85,2.35,1,\n\
+                 A1,Platform A1,48.85,2.35,0,SA\n\
+                 SB,Station B,48.86,2.35,1,\n\
+                 B1,Platform B1,48.86,2.35,0,SB\n",
+            )],
+        );
+        let timetable = GtfsTimetable::new(&gtfs);
+        let [sa, sb] = ["SA", "SB"].map(|id| stop(&timetable, id));
+
+        // no trip calls at the stations themselves
+        assert!(timetable.raptor(3, time("07:00"), sa, sb).is_empty());
+
+        let journeys = timetable.station_raptor(3, time("07:00"), sa, sb);
+        assert_eq!(journeys.len(), 1);
+        assert_eq!(journeys[0].arrival, time("08:20"));
+        let journey = timetable.station_journey(&journeys[0]);
+        assert!(matches!(
+            journey.legs[..],
+            [Leg::Transit { board_stop, alight_stop, .. }]
+                if (board_stop, alight_stop) == (sa, sb)
+        ));
+    }
+
+    #[cfg(feature = "rayon")]
+    mod parallel {
+        use super::*;
+        use crate::parallel::ParallelTimetable;
+
+        pub(super) fn network() -> Gtfs {
+            feed(
+                "parallel",
+                &[
+                    (
+                        "R1",
+                        "a1",
+                        &[("A", "08:00"), ("B", "08:10"), ("C", "08:20")],
+                    ),
+                    (
+                        "R1",
+                        "a2",
+                        &[("A", "08:30"), ("B", "08:40"), ("C", "08:50")],
+                    ),
+                    ("R2", "b1", &[("B", "08:15"), ("D", "08:30")]),
+                    ("R2", "b2", &[("C", "08:25"), ("D", "08:45")]),
+                    ("R3", "c1", &[("D", "08:35"), ("A", "08:50")]),
+                    ("R4", "d1", &[("A", "08:05"), ("D", "09:00")]),
+                ],
+                &[],
+            )
+        }
+
+        #[test]
+        fn par_raptor_finds_the_same_journeys() {
+            let gtfs = network();
+            let timetable = GtfsTimetable::new(&gtfs).with_min_change_time(120);
+            let stops = ["A", "B", "C", "D"].map(|id| stop(&timetable, id));
+
+            for tau in ["07:00", "08:05", "08:20", "08:45"].map(time) {
+                for ps in stops {
+                    for pt in stops {
+                        assert_eq!(
+                            format!("{:?}", timetable.par_raptor(3, tau, ps, pt)),
+                            format!("{:?}", timetable.raptor(3, tau, ps, pt)),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    mod batch {
+        use super::*;
+        use crate::batch::{BatchTimetable, OdQuery, TravelTime};
+
+        #[test]
+        fn batches_keep_the_order_of_the_queries() {
+            let gtfs = parallel::network();
+            let timetable = GtfsTimetable::new(&gtfs).with_min_change_time(120);
+            let stops = ["A", "B", "C", "D"].map(|id| stop(&timetable, id));
+            let departures = ["07:00", "08:05", "08:20"].map(time);
+
+            // the earliest arrival of every query, from its journey with the most trips, sources
+            // being reached at once
+            let travel_time = |ps, pt, departure| {
+                if ps == pt {
+                    return Some(TravelTime {
+                        duration: 0,
+                        trips: 0,
+                    });
+                }
+                let journeys = timetable.raptor(3, departure, ps, pt);
+                journeys.last().map(|journey| TravelTime {
+                    duration: journey.arrival - departure,
+                    trips: journey.trips(),
+                })
+            };
+
+            let mut queries = Vec::new();
+            for departure in departures {
+                for source in stops {
+                    for target in stops.iter().rev() {
+                        queries.push(OdQuery {
+                            source,
+                            target: *target,
+                            departure,
+                        });
+                    }
+                }
+            }
+            let expected = queries
+                .iter()
+                .map(|query| travel_time(query.source, query.target, query.departure))
+                .collect::<Vec<_>>();
+            assert!(
+                expected
+                    .iter()
+                    .any(|time| time.is_some_and(|time| time.trips > 1))
+            );
+            assert_eq!(timetable.batch_raptor(3, &queries), expected);
+
+            let matrix = timetable.od_matrix(3, &stops, &stops, &departures);
+            for (d, &departure) in departures.iter().enumerate() {
+                for (s, &ps) in stops.iter().enumerate() {
+                    for (t, &pt) in stops.iter().enumerate() {
+                        assert_eq!(matrix.get(d, s, t), Some(travel_time(ps, pt, departure)));
+                    }
+                }
+            }
+            assert_eq!(matrix.get(3, 0, 0), None);
+            assert_eq!(matrix.get(0, 0, 4), None);
+            assert_eq!(matrix.row(0, 4), None);
+        }
+    }
+
+    #[cfg(feature = "realtime")]
+    mod realtime {
+        use super::*;
+        use crate::alerts::ClosedTimetable;
+        use crate::realtime::RealtimeTimetable;
+        use crate::realtime::proto::trip_update::StopTimeEvent;
+        use crate::realtime::proto::trip_update::StopTimeUpdate;
+        use crate::realtime::proto::trip_update::stop_time_update::ScheduleRelationship;
+        use crate::realtime::proto::{
+            Alert, EntitySelector, FeedEntity, FeedHeader, FeedMessage, TimeRange, TripDescriptor,
+            TripUpdate, alert::Effect,
+        };
+
+        fn message(entities: impl IntoIterator<Item = FeedEntity>) -> FeedMessage {
+            FeedMessage {
+                header: FeedHeader {
+                    gtfs_realtime_version: "2.0".to_string(),
+                    ..Default::default()
+                },
+                entity: entities.into_iter().collect(),
+            }
+        }
+
+        fn date() -> NaiveDate {
+            NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()
+        }
+
+        #[test]
+        fn delays_carry_over_skipped_stops() {
+            let gtfs = feed(
+                "realtime_delays",
+                &[(
+                    "R1",
+                    "t1",
+                    &[
+                        ("A", "08:00"),
+                        ("B", "08:10"),
+                        ("C", "08:20"),
+                        ("D", "08:30"),
+                        ("E", "08:40"),
+                    ],
+                )],
+                &[],
+            );
+            let realtime =
+                RealtimeTimetable::new(GtfsTimetable::new(&gtfs).with_service_date(date()));
+            let day_start = realtime.schedule().service_day_start().unwrap();
+
+            let update = |stop: &str| StopTimeUpdate {
+                stop_id: Some(stop.to_string()),
+                ..Default::default()
+            };
+            let trip_update = TripUpdate {
+                trip: TripDescriptor {
+                    trip_id: Some("t1".to_string()),
+                    ..Default::default()
+                },
+                stop_time_update: vec![
+                    StopTimeUpdate {
+                        arrival: Some(StopTimeEvent {
+                            delay: Some(300),
+                            ..Default::default()
+                        }),
+                        ..update("B")
+                    },
+                    StopTimeUpdate {
+                        schedule_relationship: Some(ScheduleRelationship::Skipped as i32),
+                        ..update("C")
+                    },
+                    StopTimeUpdate {
+                        arrival: Some(StopTimeEvent {
+                            time: Some(day_start + time("08:50") as i64),
+                            ..Default::default()
+                        }),
+                        ..update("E")
+                    },
+                ],
+                ..Default::default()
+            };
+            realtime
+                .update(&message([FeedEntity {
+                    id: "t1".to_string(),
+                    trip_update: Some(trip_update),
+                    ..Default::default()
+                }]))
+                .unwrap();
+
+            let timetable = realtime.snapshot();
+            let [a, c, d, e] = ["A", "C", "D", "E"].map(|id| stop(&timetable, id));
+            let arrival = |to| {
+                let journeys = timetable.raptor(3, time("07:00"), a, to);
+                journeys.last().map(|journey| journey.arrival)
+            };
+
+            assert_eq!(arrival(c), None);
+            // the 5 minutes of delay of B go past the skipped stop
+            assert_eq!(arrival(d), Some(time("08:35")));
+            assert_eq!(arrival(e), Some(time("08:50")));
+        }
+
+        #[test]
+        fn updates_out_of_range_are_rejected() {
+            let gtfs = feed(
+                "realtime_out_of_range",
+                &[
+                    ("R1", "t1", &[("A", "08:00"), ("B", "08:10")]),
+                    ("R2", "f1", &[("A", "08:00"), ("C", "08:20")]),
+                ],
+                &[(
+                    "frequencies.txt",
+                    "trip_id,start_time,end_time,headway_secs,exact_times\nf1,08:00:00,09:00:00,600,1\n",
+                )],
+            );
+            let realtime =
+                RealtimeTimetable::new(GtfsTimetable::new(&gtfs).with_service_date(date()));
+            let [a, b, c] = ["A", "B", "C"].map(|id| stop(realtime.schedule(), id));
+            let arrival = |to| {
+                let timetable = realtime.snapshot();
+                let journeys = timetable.raptor(3, time("07:00"), a, to);
+                journeys.last().map(|journey| journey.arrival)
+            };
+            let trip_update = |trip: &str, start_time: Option<&str>, time| TripUpdate {
+                trip: TripDescriptor {
+                    trip_id: Some(trip.to_string()),
+                    start_time: start_time.map(str::to_string),
+                    ..Default::default()
+                },
+                stop_time_update: vec![StopTimeUpdate {
+                    stop_sequence: Some(1),
+                    arrival: Some(StopTimeEvent {
+                        time: Some(time),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let update = |trip_update| {
+                realtime.update(&message([FeedEntity {
+                    id: "1".to_string(),
+                    trip_update: Some(trip_update),
+                    ..Default::default()
+                }]))
+            };
+
+            assert!(matches!(
+                update(trip_update("t1", None, i64::MIN)),
+                Err(Error::InvalidRealtime(_))
+            ));
+            assert_eq!(arrival(b), Some(time("08:10")));
+            // past the times of the service date
+            update(trip_update("t1", None, i64::MAX)).unwrap();
+            assert_eq!(arrival(b), None);
+
+            // no run starts at that time
+            update(trip_update("f1", Some("9999999:00:00"), 0)).unwrap();
+            assert_eq!(arrival(c), Some(time("08:20")));
+        }
+
+        #[test]
+        fn alerts_close_stops_and_routes() {
+            let gtfs = feed(
+                "alert_closures",
+                &[
+                    (
+                        "R1",
+                        "a1",
+                        &[("A", "08:00"), ("B", "08:10"), ("C", "08:20")],
+                    ),
+                    ("R2", "b1", &[("A", "08:05"), ("C", "08:40")]),
+                ],
+                &[],
+            );
+            let timetable = GtfsTimetable::new(&gtfs).with_service_date(date());
+            let day_start = timetable.service_day_start().unwrap() as u64;
+            let [a, b, c] = ["A", "B", "C"].map(|id| stop(&timetable, id));
+
+            let alert = |effect: Effect, selector, period: Option<(&str, &str)>| FeedEntity {
+                id: format!("{selector:?}"),
+                alert: Some(Alert {
+                    active_period: period
+                        .map(|(start, end)| TimeRange {
+                            start: Some(day_start + time(start) as u64),
+                            end: Some(day_start + time(end) as u64),
+                        })
+                        .into_iter()
+                        .collect(),
+                    informed_entity: vec![selector],
+                    effect: Some(effect as i32),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let stop_selector = |id: &str| EntitySelector {
+                stop_id: Some(id.to_string()),
+                ..Default::default()
+            };
+            let route_selector = |id: &str| EntitySelector {
+                route_id: Some(id.to_string()),
+                ..Default::default()
+            };
+            let arrivals = |alerts| {
+                let closures = timetable
+                    .alert_closures(&message(alerts), time("08:00")..=time("09:00"))
+                    .unwrap();
+                let closed = ClosedTimetable::new(&timetable, &closures);
+                [b, c].map(|to| {
+                    let journeys = closed.raptor(3, time("07:00"), a, to);
+                    journeys.last().map(|journey| journey.arrival)
+                })
+            };
+
+            assert_eq!(arrivals(vec![]), [Some(time("08:10")), Some(time("08:20"))]);
+            // R1 still goes through B, and reduced service closes nothing
+            assert_eq!(
+                arrivals(vec![
+                    alert(Effect::NoService, stop_selector("B"), None),
+                    alert(Effect::ReducedService, route_selector("R2"), None),
+                ]),
+                [None, Some(time("08:20"))]
+            );
+            // closing C in the evening leaves the morning service alone
+            assert_eq!(
+                arrivals(vec![
+                    alert(
+                        Effect::Detour,
+                        route_selector("R1"),
+                        Some(("08:00", "09:00"))
+                    ),
+                    alert(
+                        Effect::NoService,
+                        stop_selector("C"),
+                        Some(("18:00", "19:00"))
+                    ),
+                ]),
+                [None, Some(time("08:40"))]
+            );
+        }
     }
 }
@@ -0,0 +1,541 @@
+//! Queries of a [`PrebuiltTimetable`]: the routes and runs of the service date, the footpaths and
+//! transfer rules of the stops, and the ids of the feed.
+
+use chrono::NaiveDate;
+
+use super::{DAY, DEFAULT_TRANSFER_TIME, Pattern, PrebuiltTimetable, Route, Stop, TripRun};
+use crate::reverse::ReverseTimetable;
+use crate::workspace::IndexedTimetable;
+use crate::{Error, Journey, JourneyFor, Leg, Timetable};
+
+impl PrebuiltTimetable {
+    pub fn service_date(&self) -> Option<NaiveDate> {
+        self.service_date
+    }
+
+    /// Stop times of the trip of a run, as in stop_times.txt
+    pub(super) fn template(&self, run: TripRun) -> &[(u32, u32)] {
+        let start = self.trip_stop_times[run.trip];
+        let len = self.patterns[self.trip_patterns[run.trip]].stops.len();
+
+        &self.stop_times[start..start + len]
+    }
+
+    /// How much the times of a run are moved from the times of its trip
+    pub(super) fn shift(&self, run: TripRun) -> i64 {
+        let start = match run.start {
+            Some(start) => start as i64 - self.template(run)[0].1 as i64,
+            None => 0,
+        };
+
+        if run.previous_day {
+            start - DAY as i64
+        } else {
+            start
+        }
+    }
+
+    /// Arrival and departure of a trip run at a position of its route, counted from midnight of
+    /// the service date. None if the run called there before midnight when it ran on the previous
+    /// day.
+    pub(super) fn stop_time(
+        &self,
+        run: TripRun,
+        position: usize,
+    ) -> Option<(crate::Tau, crate::Tau)> {
+        let (arrival, departure) = self.template(run)[position];
+        let shift = self.shift(run);
+
+        let arrival = crate::Tau::try_from(arrival as i64 + shift).ok()?;
+        let departure = crate::Tau::try_from(departure as i64 + shift).ok()?;
+
+        Some((arrival, departure))
+    }
+
+    /// First position of `stop` on `route`
+    pub(super) fn position(&self, route: Route, stop: Stop) -> Option<usize> {
+        let route_stops = self.stop_routes.get(stop);
+
+        route_stops
+            .binary_search_by_key(&route, |route_stop| route_stop.route)
+            .ok()
+            .map(|idx| route_stops[idx].position)
+    }
+
+    /// Whether `runs` of `route` keep their order at every stop, runs of the previous day that
+    /// haven't reached a stop yet coming first
+    pub(super) fn in_order(&self, route: Route, runs: &[TripRun]) -> bool {
+        let stop_count = self.patterns[route].stops.len();
+
+        runs.windows(2).all(|pair| {
+            (0..stop_count).all(|position| {
+                match (
+                    self.stop_time(pair[0], position),
+                    self.stop_time(pair[1], position),
+                ) {
+                    (Some((arr, dep)), Some((next_arr, next_dep))) => {
+                        arr <= next_arr && dep <= next_dep
+                    }
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                }
+            })
+        })
+    }
+
+    fn try_pattern(&self, route: Route) -> Result<&Pattern, Error> {
+        self.patterns
+            .get(route)
+            .ok_or_else(|| Error::UnknownRoute(route.to_string()))
+    }
+
+    fn try_position(&self, route: Route, stop: Stop) -> Result<usize, Error> {
+        let pattern = self.try_pattern(route)?;
+        self.check_stop(stop)?;
+
+        self.position(route, stop)
+            .ok_or_else(|| Error::StopNotOnRoute {
+                route: self.routes[pattern.route].to_string(),
+                stop: self.stops[stop].to_string(),
+            })
+    }
+
+    /// Arrival and departure of a trip run at a stop, see [`PrebuiltTimetable::stop_time`]
+    fn try_stop_time(&self, run: TripRun, stop: Stop) -> Result<(crate::Tau, crate::Tau), Error> {
+        let route = *self
+            .trip_patterns
+            .get(run.trip)
+            .ok_or_else(|| Error::UnknownTrip(run.trip.to_string()))?;
+        self.check_stop(stop)?;
+
+        // runs of the previous day don't call at the stops they reached before midnight
+        self.position(route, stop)
+            .and_then(|position| self.stop_time(run, position))
+            .ok_or_else(|| Error::TripNotAtStop {
+                trip: self.resolve_trip(run.trip).unwrap_or_default().to_string(),
+                stop: self.stops[stop].to_string(),
+            })
+    }
+
+    pub(super) fn stop_idx(&self, id: &str) -> Option<usize> {
+        self.stops
+            .binary_search_by(|item| item.as_str().cmp(id))
+            .ok()
+    }
+    pub(super) fn route_idx(&self, id: &str) -> Option<usize> {
+        self.routes
+            .binary_search_by(|item| item.as_str().cmp(id))
+            .ok()
+    }
+    pub(super) fn trip_idx(&self, id: &str) -> Option<usize> {
+        self.trips
+            .binary_search_by(|item| item.as_str().cmp(id))
+            .ok()
+    }
+
+    pub fn resolve_stop(&self, idx: usize) -> Option<&str> {
+        self.stops.get(idx).map(String::as_str)
+    }
+
+    /// GTFS route id of a route. Routes of the timetable are route patterns, so several of them
+    /// can resolve to the same GTFS route.
+    pub fn resolve_route(&self, idx: usize) -> Option<&str> {
+        let pattern = self.patterns.get(idx)?;
+        self.routes.get(pattern.route).map(String::as_str)
+    }
+
+    /// Trip id of a trip index, as found in [`TripRun::trip`]. Trips of the realtime feed come
+    /// after the trips of the schedule, and resolve to the id of the trip they replace.
+    pub fn resolve_trip(&self, idx: usize) -> Option<&str> {
+        match idx.checked_sub(self.trips.len()) {
+            Some(realtime) => self
+                .realtime_trips
+                .get(realtime)
+                .map(|trip| trip.id.as_str()),
+            None => self.trips.get(idx).map(String::as_str),
+        }
+    }
+
+    /// Trip of the schedule a trip index stands for, following realtime trips to the run they
+    /// replace
+    pub(super) fn scheduled_trip(&self, idx: usize) -> Option<usize> {
+        match idx.checked_sub(self.trips.len()) {
+            Some(realtime) => self.realtime_trips.get(realtime)?.scheduled,
+            None => Some(idx),
+        }
+    }
+
+    /// Index of the stop `id`. Stations are stops too, but no trip calls at them: search
+    /// between stations with [`PrebuiltTimetable::station_raptor`].
+    pub fn lookup_stop(&self, id: &str) -> Option<usize> {
+        self.stop_idx(id)
+    }
+
+    /// Platforms of a station, the stops whose parent station it is. Other stops, and stations
+    /// without platforms, are their own platform.
+    pub fn platforms(&self, stop: usize) -> impl Iterator<Item = usize> + '_ {
+        let platforms = self.platforms.get(stop);
+        let own = platforms.is_empty().then_some(stop);
+
+        platforms.iter().copied().chain(own)
+    }
+
+    /// Parent station of a platform, or the stop itself when it has none
+    pub fn station(&self, stop: usize) -> usize {
+        self.stations.get(stop).copied().flatten().unwrap_or(stop)
+    }
+
+    /// Panics where [`PrebuiltTimetable::try_station_raptor`] returns an error
+    pub fn station_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+        pt: usize,
+    ) -> Vec<JourneyFor<Self>> {
+        self.try_station_raptor(transfers, tau, ps, pt)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// [`Timetable::try_raptor`] between stations, from any platform of `ps` to any platform of
+    /// `pt` (see [`PrebuiltTimetable::platforms`]). Journeys start and end with access and egress
+    /// legs of no time at the platforms. [`Timetable::raptor`] finds no journey from or to a
+    /// station, as no trip calls at it.
+    pub fn try_station_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+        pt: usize,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
+
+        let access: Vec<_> = self.platforms(ps).map(|stop| (stop, 0)).collect();
+        let egress: Vec<_> = self.platforms(pt).map(|stop| (stop, 0)).collect();
+
+        self.try_raptor_multi(transfers, tau, &access, &egress)
+    }
+
+    /// `journey` at station level: stops replaced by their station (see
+    /// [`PrebuiltTimetable::station`]), without the walks within a station or to and from the
+    /// platforms of a [`PrebuiltTimetable::station_raptor`] query
+    pub fn station_journey(&self, journey: &JourneyFor<Self>) -> JourneyFor<Self> {
+        let legs = journey
+            .legs
+            .iter()
+            .filter_map(|&leg| match leg {
+                Leg::Transit {
+                    route,
+                    trip,
+                    board_stop,
+                    board_time,
+                    alight_stop,
+                    alight_time,
+                } => Some(Leg::Transit {
+                    route,
+                    trip,
+                    board_stop: self.station(board_stop),
+                    board_time,
+                    alight_stop: self.station(alight_stop),
+                    alight_time,
+                }),
+                Leg::Walk { from, to, duration } => {
+                    let (from, to) = (self.station(from), self.station(to));
+                    (from != to).then_some(Leg::Walk { from, to, duration })
+                }
+                Leg::Access { to, duration } => (duration > 0).then(|| Leg::Access {
+                    to: self.station(to),
+                    duration,
+                }),
+                Leg::Egress { from, duration } => (duration > 0).then(|| Leg::Egress {
+                    from: self.station(from),
+                    duration,
+                }),
+            })
+            .collect();
+
+        Journey { legs, ..*journey }
+    }
+
+    /// Panics where [`PrebuiltTimetable::try_earliest_arrivals_dense`] returns an error
+    pub fn earliest_arrivals_dense(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+    ) -> Vec<Option<(crate::Tau, crate::K)>> {
+        self.try_earliest_arrivals_dense(transfers, tau, ps)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Dense variant of [`Timetable::try_earliest_arrivals`], indexed by stop. Unreachable stops
+    /// are `None`.
+    pub fn try_earliest_arrivals_dense(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: usize,
+    ) -> Result<Vec<Option<(crate::Tau, crate::K)>>, Error> {
+        let mut arrivals = vec![None; self.stops.len()];
+
+        for (stop, arrival) in self.try_earliest_arrivals(transfers, tau, ps)? {
+            arrivals[stop] = Some(arrival);
+        }
+
+        Ok(arrivals)
+    }
+}
+
+impl Timetable for PrebuiltTimetable {
+    type Stop = usize;
+
+    type Route = usize;
+
+    type Trip = TripRun;
+
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> + '_ {
+        self.stop_routes
+            .get(stop)
+            .iter()
+            .map(|route_stop| route_stop.route)
+    }
+
+    fn get_earlier_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Self::Stop {
+        self.try_get_earlier_stop(route, left, right)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.try_get_stops_after(route, stop)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn get_earliest_trip(
+        &self,
+        route: Self::Route,
+        at: crate::Tau,
+        stop: Self::Stop,
+    ) -> Option<Self::Trip> {
+        let trips = self.runs.get(route);
+        let pos = self.position(route, stop)?;
+
+        let departure_at_stop =
+            |run: TripRun| -> Option<crate::Tau> { self.stop_time(run, pos).map(|(_, dep)| dep) };
+
+        if !self.runs_in_order[route] {
+            return trips
+                .iter()
+                .copied()
+                .filter(|&run| departure_at_stop(run).is_some_and(|dep| dep >= at))
+                .min_by_key(|&run| departure_at_stop(run));
+        }
+
+        // Binary search: find partition point where departure >= at
+        // runs not at this stop yet sort "before"
+        let idx = trips.partition_point(|&run| departure_at_stop(run).is_none_or(|dep| dep < at));
+
+        // Scan forward to find first trip actually serving this stop
+        trips[idx..]
+            .iter()
+            .find(|&&run| departure_at_stop(run).is_some_and(|dep| dep >= at))
+            .copied()
+    }
+
+    fn get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> crate::Tau {
+        self.try_get_arrival_time(trip, stop)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> crate::Tau {
+        self.try_get_departure_time(trip, stop)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn get_footpaths_from(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.footpaths.get(stop).iter().map(|&(to, _)| to)
+    }
+
+    fn get_transfer_time(&self, from: Self::Stop, to: Self::Stop) -> crate::Tau {
+        self.footpaths
+            .get(from)
+            .iter()
+            .find(|&&(stop, _)| stop == to)
+            .and_then(|&(_, min_transfer_time)| min_transfer_time)
+            .unwrap_or(DEFAULT_TRANSFER_TIME) as crate::Tau
+    }
+
+    // the self-transfer of the stop in transfers.txt, or the default
+    fn get_min_change_time(&self, stop: Self::Stop) -> crate::Tau {
+        self.transfers
+            .get(stop)
+            .iter()
+            .find(|transfer| transfer.from_stop == stop && transfer.specificity() == 0)
+            .and_then(|transfer| transfer.min_time)
+            .unwrap_or(self.min_change_time) as crate::Tau
+    }
+
+    fn get_trip_transfer_time(
+        &self,
+        (from_route, from_trip, from_stop): (Self::Route, Self::Trip, Self::Stop),
+        (to_route, to_trip, to_stop): (Self::Route, Self::Trip, Self::Stop),
+    ) -> Option<crate::Tau> {
+        let from_route = self.patterns.get(from_route)?.route;
+        let to_route = self.patterns.get(to_route)?.route;
+
+        self.transfers
+            .get(to_stop)
+            .iter()
+            .filter(|transfer| {
+                transfer.from_stop == from_stop
+                    && transfer.from_route.is_none_or(|route| route == from_route)
+                    && transfer.to_route.is_none_or(|route| route == to_route)
+                    && transfer
+                        .from_trip
+                        .is_none_or(|trip| Some(trip) == self.scheduled_trip(from_trip.trip))
+                    && transfer
+                        .to_trip
+                        .is_none_or(|trip| Some(trip) == self.scheduled_trip(to_trip.trip))
+            })
+            .max_by_key(|transfer| transfer.specificity())
+            .map_or_else(
+                || {
+                    let same_stop = from_stop == to_stop;
+                    Some(if same_stop {
+                        self.get_min_change_time(to_stop)
+                    } else {
+                        0
+                    })
+                },
+                |transfer| transfer.min_time.map(|min_time| min_time as crate::Tau),
+            )
+    }
+
+    fn check_stop(&self, stop: Self::Stop) -> Result<(), Error> {
+        if stop < self.stops.len() {
+            Ok(())
+        } else {
+            Err(Error::UnknownStop(stop.to_string()))
+        }
+    }
+
+    fn try_get_earlier_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Result<Self::Stop, Error> {
+        if self.try_position(route, left)? <= self.try_position(route, right)? {
+            Ok(left)
+        } else {
+            Ok(right)
+        }
+    }
+
+    fn try_get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        let pos = self.try_position(route, stop)?;
+
+        Ok(self.route_stops[self.patterns[route].stops.clone()][pos..]
+            .iter()
+            .copied())
+    }
+
+    fn try_get_arrival_time(
+        &self,
+        trip: Self::Trip,
+        stop: Self::Stop,
+    ) -> Result<crate::Tau, Error> {
+        Ok(self.try_stop_time(trip, stop)?.0)
+    }
+
+    fn try_get_departure_time(
+        &self,
+        trip: Self::Trip,
+        stop: Self::Stop,
+    ) -> Result<crate::Tau, Error> {
+        Ok(self.try_stop_time(trip, stop)?.1)
+    }
+}
+
+impl ReverseTimetable for PrebuiltTimetable {
+    fn get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.try_get_stops_before(route, stop)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn try_get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        let pos = self.try_position(route, stop)?;
+
+        Ok(self.route_stops[self.patterns[route].stops.clone()][..=pos]
+            .iter()
+            .rev()
+            .copied())
+    }
+
+    fn get_latest_trip(
+        &self,
+        route: Self::Route,
+        at: crate::Tau,
+        stop: Self::Stop,
+    ) -> Option<Self::Trip> {
+        let trips = self.runs.get(route);
+        let pos = self.position(route, stop)?;
+
+        let arrival_at_stop =
+            |run: TripRun| -> Option<crate::Tau> { self.stop_time(run, pos).map(|(arr, _)| arr) };
+
+        if !self.runs_in_order[route] {
+            return trips
+                .iter()
+                .copied()
+                .filter(|&run| arrival_at_stop(run).is_some_and(|arr| arr <= at))
+                .max_by_key(|&run| arrival_at_stop(run));
+        }
+
+        // Binary search: find partition point where arrival > at
+        // runs not at this stop yet sort "before"
+        let idx = trips.partition_point(|&run| arrival_at_stop(run).is_none_or(|arr| arr <= at));
+
+        // Scan backward to find last trip actually serving this stop
+        trips[..idx]
+            .iter()
+            .rev()
+            .find(|&&run| arrival_at_stop(run).is_some_and(|arr| arr <= at))
+            .copied()
+    }
+
+    fn get_footpaths_to(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_ {
+        self.footpaths_to.get(stop).iter().copied()
+    }
+}
+
+impl IndexedTimetable for PrebuiltTimetable {
+    fn stop_count(&self) -> usize {
+        self.stops.len()
+    }
+
+    fn route_count(&self) -> usize {
+        self.patterns.len()
+    }
+}
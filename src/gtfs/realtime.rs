@@ -0,0 +1,445 @@
+//! Trip updates of a GTFS-Realtime feed applied to the runs of the service date.
+//!
+//! Every run with realtime times becomes a trip of its own, on a route of its own: delays can make
+//! it overtake the other trips of its pattern, and skipped stops change its sequence of stops. The
+//! run of the schedule it replaces is cancelled.
+
+use std::ops::RangeInclusive;
+
+use chrono::{NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use gtfs_structures::DirectionType;
+
+use super::{
+    Adjacency, GtfsTimetable, Pattern, PrebuiltTimetable, RealtimeTrip, Route, Stop, TripRun,
+};
+use crate::alerts::Closures;
+use crate::realtime::proto::alert::Effect;
+use crate::realtime::proto::trip_descriptor::ScheduleRelationship as TripRelationship;
+use crate::realtime::proto::trip_update::StopTimeEvent;
+use crate::realtime::proto::trip_update::stop_time_update::ScheduleRelationship as StopRelationship;
+use crate::realtime::proto::{FeedMessage, TripDescriptor, TripUpdate};
+use crate::{Error, Tau};
+
+/// Stops of a run with their (arrival, departure)
+type StopTimes = Vec<(Stop, (u32, u32))>;
+
+impl<T> Adjacency<T> {
+    /// Adds the items of a new last index
+    fn push(&mut self, items: impl IntoIterator<Item = T>) {
+        self.items.extend(items);
+        self.offsets.push(self.items.len());
+    }
+}
+
+impl GtfsTimetable<'_> {
+    /// Stops and routes closed by the alerts of `feed` with no service or a detour, active at some
+    /// point of `period`, in times of the service date.
+    ///
+    /// Alerts close the stops they inform (a station closing its platforms), suspend the routes
+    /// they inform (in a single direction if given), and close a route at a stop when informing
+    /// both. Selectors of agencies, route types and trips are left out, as are unknown ids.
+    pub fn alert_closures(
+        &self,
+        feed: &FeedMessage,
+        period: RangeInclusive<Tau>,
+    ) -> Result<Closures<Route, Stop>, Error> {
+        let day_start = self.service_day_start()?;
+        let start = day_start + *period.start() as i64;
+        let end = day_start + *period.end() as i64;
+
+        let alerts = feed
+            .entity
+            .iter()
+            .filter(|entity| !entity.is_deleted())
+            .filter_map(|entity| entity.alert.as_ref())
+            .filter(|alert| matches!(alert.effect(), Effect::NoService | Effect::Detour))
+            .filter(|alert| {
+                alert.active_period.is_empty()
+                    || alert.active_period.iter().any(|range| {
+                        range
+                            .start
+                            .is_none_or(|range_start| range_start as i64 <= end)
+                            && range.end.is_none_or(|range_end| start < range_end as i64)
+                    })
+            });
+
+        let mut closures = Closures::new();
+        for selector in alerts.flat_map(|alert| &alert.informed_entity) {
+            let other_selector = selector.agency_id.is_some()
+                || selector.route_type.is_some()
+                || selector.trip.is_some();
+            if other_selector {
+                continue;
+            }
+
+            let stops = match selector.stop_id.as_deref().map(|id| self.lookup_stop(id)) {
+                Some(Some(stop)) => Some(self.platforms(stop).collect::<Vec<_>>()),
+                Some(None) => continue,
+                None => None,
+            };
+            let routes = match selector
+                .route_id
+                .as_deref()
+                .map(|id| self.prebuilt.route_idx(id))
+            {
+                Some(Some(gtfs_route)) => Some(
+                    (0..self.prebuilt.patterns.len())
+                        .filter(|&route| self.prebuilt.patterns[route].route == gtfs_route)
+                        .filter(|&route| {
+                            selector
+                                .direction_id
+                                .is_none_or(|direction| self.direction(route) == Some(direction))
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                Some(None) => continue,
+                None => None,
+            };
+
+            match (routes, stops) {
+                (Some(routes), Some(stops)) => {
+                    for &route in &routes {
+                        for &stop in &stops {
+                            closures.close_route_stop(route, stop);
+                        }
+                    }
+                }
+                (Some(routes), None) => {
+                    for route in routes {
+                        closures.suspend_route(route);
+                    }
+                }
+                (None, Some(stops)) => {
+                    for stop in stops {
+                        closures.close_stop(stop);
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+
+        Ok(closures)
+    }
+
+    /// direction_id of the trips of a route
+    fn direction(&self, route: Route) -> Option<u32> {
+        let trip = self
+            .prebuilt
+            .scheduled_trip(*self.prebuilt.pattern_trips.get(route).first()?)?;
+
+        match self.gtfs.trips[&self.prebuilt.trips[trip]].direction_id? {
+            DirectionType::Outbound => Some(0),
+            DirectionType::Inbound => Some(1),
+        }
+    }
+
+    /// POSIX time the times of the service date are counted from: noon minus 12 hours, in the
+    /// timezone of the agencies
+    pub(crate) fn service_day_start(&self) -> Result<i64, Error> {
+        let date = self.service_date().ok_or(Error::MissingServiceDate)?;
+        let agency = self
+            .gtfs
+            .agencies
+            .first()
+            .ok_or_else(|| Error::InvalidFeed("feed has no agency".to_string()))?;
+        let timezone: Tz = agency.timezone.parse().map_err(|_| {
+            Error::InvalidFeed(format!("unknown agency timezone {}", agency.timezone))
+        })?;
+
+        let noon = date.and_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap());
+        let noon = timezone
+            .from_local_datetime(&noon)
+            .earliest()
+            .ok_or_else(|| {
+                Error::InvalidFeed(format!("no noon on {date} in {}", agency.timezone))
+            })?;
+
+        Ok(noon.timestamp() - 12 * 3600)
+    }
+
+    /// A copy of this timetable with the trip `updates` applied, POSIX times of the feed being
+    /// counted from `day_start`. The first update of a run wins over the next ones. Fails on
+    /// updates with times out of range.
+    pub(crate) fn with_trip_updates<'u>(
+        &self,
+        updates: impl IntoIterator<Item = &'u TripUpdate>,
+        day_start: i64,
+    ) -> Result<Self, Error> {
+        let mut timetable = self.clone();
+
+        for update in updates {
+            match update.trip.schedule_relationship() {
+                TripRelationship::Scheduled | TripRelationship::Unscheduled => {
+                    let Some(run) = self.scheduled_run(&update.trip) else {
+                        continue;
+                    };
+                    let Some(stop_times) = self.updated_stop_times(run, update, day_start)? else {
+                        continue;
+                    };
+
+                    if timetable.cancelled_runs.insert(run) {
+                        let route =
+                            self.prebuilt.patterns[self.prebuilt.trip_patterns[run.trip]].route;
+                        let trip = RealtimeTrip {
+                            id: self.prebuilt.trips[run.trip].clone(),
+                            scheduled: Some(run.trip),
+                        };
+                        timetable
+                            .prebuilt
+                            .push_realtime_trip(trip, route, stop_times);
+                    }
+                }
+                TripRelationship::Canceled | TripRelationship::Deleted => {
+                    if let Some(run) = self.scheduled_run(&update.trip) {
+                        timetable.cancelled_runs.insert(run);
+                    }
+                }
+                TripRelationship::Added | TripRelationship::New => {
+                    let trip_id = update.trip.trip_id.as_deref();
+                    let route_id = update.trip.route_id.as_deref();
+                    let (Some(trip_id), Some(route)) =
+                        (trip_id, route_id.and_then(|id| self.prebuilt.route_idx(id)))
+                    else {
+                        continue;
+                    };
+
+                    let stop_times = self.added_stop_times(update, day_start)?;
+                    if !stop_times.is_empty() {
+                        let trip = RealtimeTrip {
+                            id: trip_id.to_string(),
+                            scheduled: None,
+                        };
+                        timetable
+                            .prebuilt
+                            .push_realtime_trip(trip, route, stop_times);
+                    }
+                }
+                // replacement and duplicated trips aren't supported
+                TripRelationship::Replacement | TripRelationship::Duplicated => {}
+            }
+        }
+
+        timetable.build_stop_routes();
+        timetable.build_runs();
+        Ok(timetable)
+    }
+
+    /// Run of the schedule a trip descriptor refers to, if it runs on the service date. Runs of
+    /// frequency-based trips are told apart by their start time.
+    fn scheduled_run(&self, descriptor: &TripDescriptor) -> Option<TripRun> {
+        let trip = self.prebuilt.trip_idx(descriptor.trip_id.as_deref()?)?;
+        let date = self.service_date()?;
+
+        let previous_day = match descriptor.start_date.as_deref() {
+            None => false,
+            Some(start_date) => {
+                let start_date = NaiveDate::parse_from_str(start_date, "%Y%m%d").ok()?;
+                if start_date == date {
+                    false
+                } else if Some(start_date) == date.pred_opt() {
+                    true
+                } else {
+                    return None;
+                }
+            }
+        };
+        let start = if self.gtfs.trips[&self.prebuilt.trips[trip]]
+            .frequencies
+            .is_empty()
+        {
+            None
+        } else {
+            Some(parse_time(descriptor.start_time.as_deref()?)?)
+        };
+
+        let run = TripRun {
+            trip,
+            previous_day,
+            start,
+        };
+        let route = self.prebuilt.trip_patterns[trip];
+        self.prebuilt.runs.get(route).contains(&run).then_some(run)
+    }
+
+    /// Stops of a run and their times with the stop time updates, skipped stops left out. None if
+    /// the run keeps to the schedule.
+    ///
+    /// The delay of a stop carries over to the next stops, until a stop with an update of its own.
+    fn updated_stop_times(
+        &self,
+        run: TripRun,
+        update: &TripUpdate,
+        day_start: i64,
+    ) -> Result<Option<StopTimes>, Error> {
+        let route = self.prebuilt.trip_patterns[run.trip];
+        let stops = &self.prebuilt.route_stops[self.prebuilt.patterns[route].stops.clone()];
+        let gtfs_stop_times = &self.gtfs.trips[&self.prebuilt.trips[run.trip]].stop_times;
+
+        // update of every position, stop time updates being sorted by stop sequence
+        let mut position_updates = vec![None; stops.len()];
+        let mut next = 0;
+        for stop_time_update in &update.stop_time_update {
+            let position =
+                (next..stops.len()).find(|&position| match stop_time_update.stop_sequence {
+                    Some(sequence) => gtfs_stop_times[position].stop_sequence == sequence,
+                    None => {
+                        stop_time_update.stop_id.as_deref()
+                            == Some(self.prebuilt.stops[stops[position]].as_str())
+                    }
+                });
+            if let Some(position) = position {
+                position_updates[position] = Some(stop_time_update);
+                next = position + 1;
+            }
+        }
+
+        let out_of_range = || out_of_range(&self.prebuilt.trips[run.trip]);
+        let event_time = |event: Option<StopTimeEvent>, scheduled: i64| {
+            let Some(event) = event else {
+                return Ok(None);
+            };
+            let time = match (event.time, event.delay) {
+                (Some(time), _) => time.checked_sub(day_start),
+                (None, Some(delay)) => scheduled.checked_add(delay.into()),
+                (None, None) => return Ok(None),
+            };
+            time.map(Some).ok_or_else(out_of_range)
+        };
+
+        let shift = self.prebuilt.shift(run);
+        let mut delay = update.delay.map_or(0, i64::from);
+        let mut changed = false;
+        let mut stop_times = Vec::new();
+
+        for (position, &stop) in stops.iter().enumerate() {
+            let (arrival, departure) = self.prebuilt.template(run)[position];
+            let arrival = arrival as i64 + shift;
+            let departure = departure as i64 + shift;
+
+            let stop_time_update = position_updates[position];
+            if let Some(stop_time_update) = stop_time_update {
+                match stop_time_update.schedule_relationship() {
+                    StopRelationship::Skipped => {
+                        changed = true;
+                        continue;
+                    }
+                    StopRelationship::NoData => delay = 0,
+                    StopRelationship::Scheduled | StopRelationship::Unscheduled => {}
+                }
+            }
+
+            if let Some(stu) = stop_time_update
+                && let Some(time) = event_time(stu.arrival, arrival)?
+            {
+                delay = time.checked_sub(arrival).ok_or_else(out_of_range)?;
+            }
+            let realtime_arrival = arrival.checked_add(delay).ok_or_else(out_of_range)?;
+            if let Some(stu) = stop_time_update
+                && let Some(time) = event_time(stu.departure, departure)?
+            {
+                delay = time.checked_sub(departure).ok_or_else(out_of_range)?;
+            }
+            let realtime_departure = departure.checked_add(delay).ok_or_else(out_of_range)?;
+
+            changed |= (realtime_arrival, realtime_departure) != (arrival, departure);
+            stop_times.push((stop, realtime_arrival, realtime_departure));
+        }
+
+        Ok(changed.then(|| service_date_stop_times(stop_times)))
+    }
+
+    /// Stops of an added trip and their times, from the stop time updates with a POSIX time
+    fn added_stop_times(&self, update: &TripUpdate, day_start: i64) -> Result<StopTimes, Error> {
+        let trip_id = update.trip.trip_id.as_deref().unwrap_or_default();
+        let since_day_start = |time: i64| {
+            time.checked_sub(day_start)
+                .ok_or_else(|| out_of_range(trip_id))
+        };
+
+        let mut stop_times = Vec::new();
+        for stu in &update.stop_time_update {
+            if stu.schedule_relationship() == StopRelationship::Skipped {
+                continue;
+            }
+            let Some(stop) = stu
+                .stop_id
+                .as_deref()
+                .and_then(|id| self.prebuilt.stop_idx(id))
+            else {
+                continue;
+            };
+            let arrival = stu.arrival.and_then(|event| event.time);
+            let departure = stu.departure.and_then(|event| event.time);
+            let Some(arrival) = arrival.or(departure) else {
+                continue;
+            };
+
+            let arrival = since_day_start(arrival)?;
+            let departure = departure.map_or(Ok(arrival), since_day_start)?;
+            stop_times.push((stop, arrival, departure));
+        }
+
+        Ok(service_date_stop_times(stop_times))
+    }
+}
+
+impl PrebuiltTimetable {
+    fn push_realtime_trip(&mut self, trip: RealtimeTrip, route: usize, stop_times: StopTimes) {
+        let start = self.route_stops.len();
+        self.route_stops
+            .extend(stop_times.iter().map(|&(stop, _)| stop));
+        self.patterns.push(Pattern {
+            route,
+            stops: start..self.route_stops.len(),
+        });
+
+        let trip_idx = self.trips.len() + self.realtime_trips.len();
+        self.realtime_trips.push(trip);
+        self.trip_patterns.push(self.patterns.len() - 1);
+        self.trip_stop_times.push(self.stop_times.len());
+        self.stop_times
+            .extend(stop_times.iter().map(|&(_, times)| times));
+        self.pattern_trips.push([trip_idx]);
+    }
+}
+
+/// Stop times counted from the start of the service date, the stops called at before it left out.
+/// Times never go back along the trip.
+fn service_date_stop_times(stop_times: impl IntoIterator<Item = (Stop, i64, i64)>) -> StopTimes {
+    let mut last = i64::MIN;
+
+    stop_times
+        .into_iter()
+        .filter_map(|(stop, arrival, departure)| {
+            let arrival = arrival.max(last);
+            let departure = departure.max(arrival);
+            last = departure;
+
+            Some((
+                stop,
+                (u32::try_from(arrival).ok()?, u32::try_from(departure).ok()?),
+            ))
+        })
+        .collect()
+}
+
+/// Error of an update of `trip_id` with times out of range
+fn out_of_range(trip_id: &str) -> Error {
+    Error::InvalidRealtime(format!("times of trip {trip_id} out of range"))
+}
+
+/// Seconds of a time of the day in the HH:MM:SS format, hours going past 24
+fn parse_time(time: &str) -> Option<u32> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)?
+        .checked_add(seconds)
+}
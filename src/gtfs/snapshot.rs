@@ -0,0 +1,168 @@
+//! Snapshots of a [`PrebuiltTimetable`] as compact binary files, with the `serde` feature.
+//!
+//! A snapshot starts with a header: magic bytes, then the version of the snapshot format and the
+//! version of the crate that wrote it. Snapshots of other versions are rejected as stale, the
+//! layout of the timetable can change between them, and have to be built again from the feed.
+
+use std::{fs, path::Path};
+
+use bincode::config::{self, Configuration};
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::PrebuiltTimetable;
+use crate::Error;
+
+const MAGIC: &[u8; 8] = b"RAPTORTT";
+// bumped on every change of the layout of the timetable
+const FORMAT_VERSION: u32 = 1;
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+const CONFIG: Configuration = config::standard();
+
+impl PrebuiltTimetable {
+    /// The snapshot of the timetable, see [`PrebuiltTimetable::save`]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = MAGIC.to_vec();
+        encode(&(FORMAT_VERSION, CRATE_VERSION), &mut bytes)?;
+        encode(self, &mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    /// Reads a timetable from its snapshot, failing on snapshots written by another version
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| Error::InvalidSnapshot("not a timetable snapshot".to_string()))?;
+
+        let ((format_version, crate_version), header_len): ((u32, String), _) = decode(bytes)?;
+        if (format_version, crate_version.as_str()) != (FORMAT_VERSION, CRATE_VERSION) {
+            return Err(Error::StaleSnapshot {
+                found: version(format_version, &crate_version),
+                expected: version(FORMAT_VERSION, CRATE_VERSION),
+            });
+        }
+
+        let (timetable, len) = decode(&bytes[header_len..])?;
+        if header_len + len != bytes.len() {
+            return Err(Error::InvalidSnapshot(
+                "trailing bytes after the timetable".to_string(),
+            ));
+        }
+
+        Ok(timetable)
+    }
+
+    /// Writes the snapshot of the timetable to the file at `path`, to be loaded back with
+    /// [`PrebuiltTimetable::load`] instead of building the timetable from the feed again
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()?)
+            .map_err(|err| Error::InvalidSnapshot(format!("can't write {}: {err}", path.display())))
+    }
+
+    /// Reads a timetable from the snapshot file at `path`, see [`PrebuiltTimetable::from_bytes`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|err| {
+            Error::InvalidSnapshot(format!("can't read {}: {err}", path.display()))
+        })?;
+
+        Self::from_bytes(&bytes)
+    }
+}
+
+fn encode(value: &impl Serialize, bytes: &mut Vec<u8>) -> Result<(), Error> {
+    bincode::serde::encode_into_std_write(value, bytes, CONFIG)
+        .map(|_| ())
+        .map_err(|err| Error::InvalidSnapshot(format!("can't encode: {err}")))
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<(T, usize), Error> {
+    bincode::serde::decode_from_slice(bytes, CONFIG)
+        .map_err(|err| Error::InvalidSnapshot(format!("can't decode: {err}")))
+}
+
+fn version(format_version: u32, crate_version: &str) -> String {
+    format!("format {format_version} of raptor {crate_version}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timetable;
+    use crate::gtfs::GtfsTimetable;
+    use crate::gtfs::tests::feed;
+
+    fn timetable() -> PrebuiltTimetable {
+        let gtfs = feed(
+            "snapshot",
+            &[("R1", "a1", &[("A", "08:00"), ("B", "08:10")])],
+            &[],
+        );
+        GtfsTimetable::new(&gtfs).into_prebuilt()
+    }
+
+    /// A snapshot of `timetable` with the header of another version
+    fn snapshot_of_version(timetable: &PrebuiltTimetable, version: (u32, &str)) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        encode(&version, &mut bytes).unwrap();
+        encode(timetable, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn loads_saved_timetable() {
+        let timetable = timetable();
+        let loaded = PrebuiltTimetable::from_bytes(&timetable.to_bytes().unwrap()).unwrap();
+        let (a, b) = (
+            loaded.lookup_stop("A").unwrap(),
+            loaded.lookup_stop("B").unwrap(),
+        );
+
+        let journeys = loaded.raptor(3, 7 * 3600, a, b);
+        assert_eq!(journeys.len(), 1);
+        assert_eq!(journeys[0].arrival, 8 * 3600 + 600);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = timetable().to_bytes().unwrap();
+        bytes[0] = b'X';
+
+        assert!(matches!(
+            PrebuiltTimetable::from_bytes(&bytes),
+            Err(Error::InvalidSnapshot(_))
+        ));
+        assert!(matches!(
+            PrebuiltTimetable::from_bytes(&[]),
+            Err(Error::InvalidSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let timetable = timetable();
+
+        for version in [
+            (FORMAT_VERSION + 1, CRATE_VERSION),
+            (FORMAT_VERSION, "0.0.0"),
+        ] {
+            let bytes = snapshot_of_version(&timetable, version);
+            assert!(matches!(
+                PrebuiltTimetable::from_bytes(&bytes),
+                Err(Error::StaleSnapshot { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut bytes = timetable().to_bytes().unwrap();
+        bytes.push(0);
+
+        assert!(matches!(
+            PrebuiltTimetable::from_bytes(&bytes),
+            Err(Error::InvalidSnapshot(_))
+        ));
+    }
+}
@@ -1,61 +1,730 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
+use std::ops::RangeInclusive;
 
+pub mod alerts;
+#[cfg(feature = "rayon")]
+pub mod batch;
+mod error;
 pub mod gtfs;
+pub mod mc;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+mod query;
+#[cfg(feature = "realtime")]
+pub mod realtime;
+pub mod reverse;
+pub mod workspace;
+
+pub use error::Error;
+pub use query::RaptorQuery;
 
 pub type K = usize;
 pub type Tau = usize;
 
+/// A single leg of a journey, either riding a trip or walking a footpath. Journeys of
+/// [`Timetable::raptor_multi`] also walk from the origin and to the destination of the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leg<Route, Trip, Stop> {
+    Transit {
+        route: Route,
+        trip: Trip,
+        board_stop: Stop,
+        board_time: Tau,
+        alight_stop: Stop,
+        alight_time: Tau,
+    },
+    Walk {
+        from: Stop,
+        to: Stop,
+        duration: Tau,
+    },
+    Access {
+        to: Stop,
+        duration: Tau,
+    },
+    Egress {
+        from: Stop,
+        duration: Tau,
+    },
+}
+
 #[derive(Debug, Clone)]
-pub struct Journey<Route, Stop> {
-    pub plan: Vec<(Route, Stop)>,
+pub struct Journey<Route, Trip, Stop> {
+    pub legs: Vec<Leg<Route, Trip, Stop>>,
+    pub departure: Tau,
     pub arrival: Tau,
 }
 
-type BoardingTree<Route, Stop> = BTreeMap<(K, Stop), (Stop, Route)>;
+/// [`Journey`] found in timetable `T`
+pub type JourneyFor<T> =
+    Journey<<T as Timetable>::Route, <T as Timetable>::Trip, <T as Timetable>::Stop>;
+
+impl<Route, Trip, Stop> Journey<Route, Trip, Stop> {
+    /// Number of trips ridden, one more than the number of transfers
+    pub fn trips(&self) -> usize {
+        self.legs
+            .iter()
+            .filter(|leg| matches!(leg, Leg::Transit { .. }))
+            .count()
+    }
+}
+
+/// The arrival at a stop in some round, along with the leg that got us there
+#[derive(Debug, Clone, Copy)]
+struct Label<Route, Trip, Stop> {
+    arrival: Tau,
+    // None for the source
+    leg: Option<Leg<Route, Trip, Stop>>,
+}
+
+/// Storage of the multilabels of all the stops, τ_k(p) in the paper.
+///
+/// Labels are only stored for the round in which they were found, and τ_k(p) is the label of the
+/// latest round <= k, which gives the "at most k trips" semantics of the paper without having to
+/// copy labels over between rounds.
+trait LabelStore<Route, Trip, Stop> {
+    /// Label for `stop` with at most `k` trips, along with the round it was found in
+    fn get(&self, stop: Stop, k: K) -> Option<(K, Label<Route, Trip, Stop>)>;
+
+    /// τ_k(stop)
+    fn arrival(&self, stop: Stop, k: K) -> Tau {
+        self.get(stop, k)
+            .map(|(_, label)| label.arrival)
+            .unwrap_or(Tau::MAX)
+    }
+
+    /// Stores the label found for `stop` in round `k`. Labels of later rounds that are no better
+    /// are now dominated, and removed. These only exist when labels are reused across runs, like
+    /// in rRAPTOR
+    fn insert(&mut self, stop: Stop, k: K, label: Label<Route, Trip, Stop>);
+
+    /// Best label of every stop reached, along with the round it was found in
+    fn best(&self) -> impl Iterator<Item = (Stop, K, Label<Route, Trip, Stop>)> + '_;
+}
+
+impl<L, Route, Trip, Stop> LabelStore<Route, Trip, Stop> for &mut L
+where
+    L: LabelStore<Route, Trip, Stop>,
+{
+    fn get(&self, stop: Stop, k: K) -> Option<(K, Label<Route, Trip, Stop>)> {
+        (**self).get(stop, k)
+    }
+
+    fn insert(&mut self, stop: Stop, k: K, label: Label<Route, Trip, Stop>) {
+        (**self).insert(stop, k, label);
+    }
+
+    fn best(&self) -> impl Iterator<Item = (Stop, K, Label<Route, Trip, Stop>)> + '_ {
+        (**self).best()
+    }
+}
+
+/// Set of the stops marked in a round
+trait StopSet<Stop> {
+    fn insert(&mut self, stop: Stop);
+    fn clear(&mut self);
+    fn is_empty(&self) -> bool;
+    fn iter(&self) -> impl Iterator<Item = Stop> + '_;
+}
+
+impl<S: StopSet<Stop>, Stop> StopSet<Stop> for &mut S {
+    fn insert(&mut self, stop: Stop) {
+        (**self).insert(stop);
+    }
+
+    fn clear(&mut self) {
+        (**self).clear();
+    }
+
+    fn is_empty(&self) -> bool {
+        (**self).is_empty()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Stop> + '_ {
+        (**self).iter()
+    }
+}
+
+impl<Stop: Ord + Copy> StopSet<Stop> for BTreeSet<Stop> {
+    fn insert(&mut self, stop: Stop) {
+        BTreeSet::insert(self, stop);
+    }
+
+    fn clear(&mut self) {
+        BTreeSet::clear(self);
+    }
+
+    fn is_empty(&self) -> bool {
+        BTreeSet::is_empty(self)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Stop> + '_ {
+        BTreeSet::iter(self).copied()
+    }
+}
+
+/// Routes to scan in a round, each from the earliest marked stop of the route. Q in the paper
+trait RouteQueue<Route, Stop> {
+    fn get(&self, route: Route) -> Option<Stop>;
+    fn insert(&mut self, route: Route, stop: Stop);
+    fn clear(&mut self);
+    /// Puts the routes in order once queued, for queues that don't keep them sorted. Routes are
+    /// scanned in order, which picks the same journey among equally good ones whatever the queue.
+    fn sort(&mut self) {}
+    fn iter(&self) -> impl Iterator<Item = (Route, Stop)> + '_;
+}
+
+impl<Q: RouteQueue<Route, Stop>, Route, Stop> RouteQueue<Route, Stop> for &mut Q {
+    fn get(&self, route: Route) -> Option<Stop> {
+        (**self).get(route)
+    }
+
+    fn insert(&mut self, route: Route, stop: Stop) {
+        (**self).insert(route, stop);
+    }
+
+    fn clear(&mut self) {
+        (**self).clear();
+    }
+
+    fn sort(&mut self) {
+        (**self).sort();
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Route, Stop)> + '_ {
+        (**self).iter()
+    }
+}
+
+impl<Route: Ord + Copy, Stop: Copy> RouteQueue<Route, Stop> for BTreeMap<Route, Stop> {
+    fn get(&self, route: Route) -> Option<Stop> {
+        BTreeMap::get(self, &route).copied()
+    }
+
+    fn insert(&mut self, route: Route, stop: Stop) {
+        BTreeMap::insert(self, route, stop);
+    }
+
+    fn clear(&mut self) {
+        BTreeMap::clear(self);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Route, Stop)> + '_ {
+        BTreeMap::iter(self).map(|(&route, &stop)| (route, stop))
+    }
+}
+
+/// Labels of arbitrary stops, kept in a map
+struct Labels<Route, Trip, Stop> {
+    inner: BTreeMap<(Stop, K), Label<Route, Trip, Stop>>,
+}
+
+impl<Route, Trip, Stop> Labels<Route, Trip, Stop> {
+    fn new() -> Self {
+        Self {
+            inner: BTreeMap::new(),
+        }
+    }
+}
+
+impl<Route, Trip, Stop> LabelStore<Route, Trip, Stop> for Labels<Route, Trip, Stop>
+where
+    Stop: Ord + Copy,
+    Route: Copy,
+    Trip: Copy,
+{
+    fn get(&self, stop: Stop, k: K) -> Option<(K, Label<Route, Trip, Stop>)> {
+        self.inner
+            .range((stop, 0)..=(stop, k))
+            .next_back()
+            .map(|(&(_, round), &label)| (round, label))
+    }
+
+    fn insert(&mut self, stop: Stop, k: K, label: Label<Route, Trip, Stop>) {
+        let dominated: Vec<K> = self
+            .inner
+            .range((stop, k + 1)..=(stop, K::MAX))
+            .filter(|(_, later)| later.arrival >= label.arrival)
+            .map(|(&(_, round), _)| round)
+            .collect();
+
+        for round in dominated {
+            self.inner.remove(&(stop, round));
+        }
+
+        self.inner.insert((stop, k), label);
+    }
 
-fn reconstruct_journey<R, S>(
-    tree: &BoardingTree<R, S>,
-    ps: S,
-    pt: S,
+    fn best(&self) -> impl Iterator<Item = (Stop, K, Label<Route, Trip, Stop>)> + '_ {
+        let mut iter = self.inner.iter().peekable();
+
+        std::iter::from_fn(move || {
+            loop {
+                let (&(stop, round), &label) = iter.next()?;
+                // labels of a stop are sorted by round, and later rounds only hold better labels
+                if iter.peek().is_none_or(|((next, _), _)| *next != stop) {
+                    return Some((stop, round, label));
+                }
+            }
+        })
+    }
+}
+
+/// Arrivals at stops found while scanning routes, along with their labels
+type Arrivals<T> = Vec<(
+    <T as Timetable>::Stop,
+    Label<<T as Timetable>::Route, <T as Timetable>::Trip, <T as Timetable>::Stop>,
+)>;
+
+/// State of a RAPTOR search, which can be run several times from different departures (rRAPTOR)
+/// while reusing the labels of the previous runs. Targets are given with their egress time, and
+/// without targets the search is one-to-all.
+struct Search<
+    'tt,
+    T: Timetable + ?Sized,
+    L = Labels<<T as Timetable>::Route, <T as Timetable>::Trip, <T as Timetable>::Stop>,
+    M = BTreeSet<<T as Timetable>::Stop>,
+    Q = BTreeMap<<T as Timetable>::Route, <T as Timetable>::Stop>,
+> {
+    timetable: &'tt T,
     transfers: K,
-) -> Vec<Vec<(R, S)>>
+    targets: &'tt [(T::Stop, Tau)],
+    // stops have to be reached before this time
+    arrival_limit: Tau,
+    target_pruning: bool,
+    labels: L,
+    marked_stops: M,
+    queue: Q,
+}
+
+impl<'tt, T: Timetable + ?Sized> Search<'tt, T> {
+    fn new(timetable: &'tt T, transfers: K, targets: &'tt [(T::Stop, Tau)]) -> Self {
+        Self::with_state(
+            timetable,
+            transfers,
+            targets,
+            Labels::new(),
+            BTreeSet::new(),
+            BTreeMap::new(),
+        )
+    }
+}
+
+impl<'tt, T, L, M, Q> Search<'tt, T, L, M, Q>
 where
-    S: Ord + Copy + Debug,
-    R: Copy + Debug,
+    T: Timetable + ?Sized,
+    L: LabelStore<T::Route, T::Trip, T::Stop>,
+    M: StopSet<T::Stop>,
+    Q: RouteQueue<T::Route, T::Stop>,
 {
-    if tree.is_empty() {
-        // Either no trips were taken, or we never reached target. The latter is
-        // possible if ps and pt are nodes of a disjoint graph
-        return Default::default();
+    /// A search keeping its labels, marked stops and route queue in the given, empty, storage
+    fn with_state(
+        timetable: &'tt T,
+        transfers: K,
+        targets: &'tt [(T::Stop, Tau)],
+        labels: L,
+        marked_stops: M,
+        queue: Q,
+    ) -> Self {
+        Self {
+            timetable,
+            transfers,
+            targets,
+            arrival_limit: Tau::MAX,
+            target_pruning: true,
+            labels,
+            marked_stops,
+            queue,
+        }
     }
 
-    let mut plans = Vec::new();
+    /// Applies the arrival limit and target pruning of `query`
+    fn limit(mut self, query: &RaptorQuery<T::Stop>) -> Self {
+        self.arrival_limit = query.arrival_limit();
+        self.target_pruning = query.target_pruning;
+        self
+    }
 
-    for k in 1..=transfers {
-        let mut plan = Vec::with_capacity(k);
-        let mut parent = pt;
+    /// Arrival at the destination with at most `k` trips, the best τ_k(pt) plus egress time of
+    /// the targets, used for target pruning
+    fn target_arrival(&self, k: K) -> Tau {
+        self.targets
+            .iter()
+            .map(|&(pt, egress)| self.labels.arrival(pt, k).saturating_add(egress))
+            .min()
+            .unwrap_or(Tau::MAX)
+    }
 
-        for inner_k in (1..=k).rev() {
-            if parent == ps {
-                break;
+    /// Labels of round `k` have to arrive before this time, following the arrival limit and
+    /// target pruning
+    fn arrival_bound(&self, k: K) -> Tau {
+        if self.target_pruning {
+            self.target_arrival(k).min(self.arrival_limit)
+        } else {
+            self.arrival_limit
+        }
+    }
+
+    /// Egress time from `stop`, if it is a target
+    fn egress(&self, stop: T::Stop) -> Option<Tau> {
+        self.targets
+            .iter()
+            .filter(|&&(pt, _)| pt == stop)
+            .map(|&(_, egress)| egress)
+            .min()
+    }
+
+    /// The target reached with at most `k` trips that gets to the destination the earliest,
+    /// along with its egress time
+    fn best_target(&self, k: K) -> Option<(T::Stop, Tau)> {
+        self.targets
+            .iter()
+            .copied()
+            .filter(|&(pt, _)| self.labels.get(pt, k).is_some())
+            .min_by_key(|&(pt, egress)| self.labels.arrival(pt, k).saturating_add(egress))
+    }
+
+    /// Runs all the rounds departing from `ps` at `tau`, and returns the rounds in which the
+    /// target was improved
+    fn run(&mut self, ps: T::Stop, tau: Tau) -> Result<Vec<K>, Error> {
+        let source = Label {
+            arrival: tau,
+            leg: None,
+        };
+
+        self.run_from([(ps, source)])
+    }
+
+    /// Runs all the rounds from the labels of the `sources`, and returns the rounds in which the
+    /// arrival at the destination was improved
+    fn run_from(
+        &mut self,
+        sources: impl IntoIterator<Item = (T::Stop, Label<T::Route, T::Trip, T::Stop>)>,
+    ) -> Result<Vec<K>, Error> {
+        self.run_from_with(sources, Self::scan_routes)
+    }
+
+    /// [`Search::run_from`], scanning the routes of every round with `scan_routes`
+    fn run_from_with(
+        &mut self,
+        sources: impl IntoIterator<Item = (T::Stop, Label<T::Route, T::Trip, T::Stop>)>,
+        scan_routes: impl Fn(&Self, K, &mut Arrivals<T>) -> Result<(), Error>,
+    ) -> Result<Vec<K>, Error> {
+        let tt = self.timetable;
+        let mut improved_target = Vec::new();
+        let mut arrivals = Vec::new();
+
+        self.marked_stops.clear();
+        for (stop, label) in sources {
+            if label.arrival < self.labels.arrival(stop, 0) {
+                self.labels.insert(stop, 0, label);
+                self.marked_stops.insert(stop);
+            }
+        }
+
+        for k in 1..=self.transfers {
+            self.queue.clear();
+            // find all routes that serve the marked stops, for evaluation in this round
+            for marked_stop in self.marked_stops.iter() {
+                for route in tt.get_routes_serving_stop(marked_stop) {
+                    let p_dash = match self.queue.get(route) {
+                        Some(p_dash) => tt.try_get_earlier_stop(route, marked_stop, p_dash)?,
+                        None => marked_stop,
+                    };
+
+                    self.queue.insert(route, p_dash);
+                }
+            }
+            self.queue.sort();
+
+            self.marked_stops.clear();
+
+            // scanning each route, then keeping the arrivals that still improve in the order of
+            // the routes
+            scan_routes(self, k, &mut arrivals)?;
+            for (stop, label) in arrivals.drain(..) {
+                if self.improve(stop, k, label, &mut improved_target) {
+                    self.marked_stops.insert(stop);
+                }
+            }
+
+            // look at footpaths from the stops reached by riding in this round, and mark the
+            // stops reachable
+            let mut walks = Vec::new();
+            for stop in self.marked_stops.iter() {
+                let arrival_at_stop = self.labels.arrival(stop, k);
+
+                for p_dash in tt.get_footpaths_from(stop) {
+                    let duration = tt.get_transfer_time(stop, p_dash);
+                    walks.push((
+                        p_dash,
+                        stop,
+                        arrival_at_stop.saturating_add(duration),
+                        duration,
+                    ));
+                }
+            }
+
+            let mut more_marked_stops = Vec::new();
+            for (p_dash, from, tau, duration) in walks {
+                let label = Label {
+                    arrival: tau,
+                    leg: Some(Leg::Walk {
+                        from,
+                        to: p_dash,
+                        duration,
+                    }),
+                };
+
+                if self.improve(p_dash, k, label, &mut improved_target) {
+                    more_marked_stops.push(p_dash);
+                }
+            }
+
+            for stop in more_marked_stops {
+                self.marked_stops.insert(stop);
             }
 
-            let Some((stop, route)) = tree.get(&(inner_k, parent)).copied() else {
+            if self.marked_stops.is_empty() {
                 break;
+            }
+        }
+
+        improved_target.dedup();
+        Ok(improved_target)
+    }
+
+    /// Scans the routes of Q in round `k`, see [`Search::scan_route`]
+    fn scan_routes(&self, k: K, arrivals: &mut Arrivals<T>) -> Result<(), Error> {
+        for (route, p) in self.queue.iter() {
+            self.scan_route(route, p, k, arrivals)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rides `route` from `p` in round `k`, and collects the arrivals at the stops after `p` that
+    /// improve on the labels as they were at the start of the round. Routes only read the labels,
+    /// so they can be scanned in any order, or at the same time.
+    fn scan_route(
+        &self,
+        route: T::Route,
+        p: T::Stop,
+        k: K,
+        arrivals: &mut Arrivals<T>,
+    ) -> Result<(), Error> {
+        let tt = self.timetable;
+        let mut current_trip: Option<T::Trip> = None;
+        let mut boarding_stop = p;
+        let mut boarding_time = Tau::MAX;
+
+        for pi in tt.try_get_stops_after(route, p)? {
+            if let Some(trip) = current_trip {
+                let arr = tt.try_get_arrival_time(trip, pi)?;
+                // τ_k(pi) already accounts for every earlier round (and for later departures in
+                // rRAPTOR), so it doubles as the local pruning bound
+                let time_to_beat = self.labels.arrival(pi, k).min(self.arrival_bound(k));
+
+                if arr < time_to_beat {
+                    let label = Label {
+                        arrival: arr,
+                        leg: Some(Leg::Transit {
+                            route,
+                            trip,
+                            board_stop: boarding_stop,
+                            board_time: boarding_time,
+                            alight_stop: pi,
+                            alight_time: arr,
+                        }),
+                    };
+                    arrivals.push((pi, label));
+                }
+            }
+
+            let t_prev_pi = self.labels.arrival(pi, k - 1);
+            let current_departure = match current_trip {
+                Some(trip) => tt.try_get_departure_time(trip, pi)?,
+                None => Tau::MAX,
             };
+            if t_prev_pi <= current_departure {
+                let trip = self.earliest_trip(route, pi, t_prev_pi, k - 1)?;
+                let departure = match trip {
+                    Some(trip) => tt.try_get_departure_time(trip, pi)?,
+                    None => Tau::MAX,
+                };
+
+                // transfer rules can leave only later trips than the current one to change to
+                if departure <= current_departure {
+                    current_trip = trip;
+                    boarding_stop = pi;
+                    boarding_time = departure;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The earliest trip of `route` departing from `stop` after `tau`, the arrival there in round
+    /// `k`, that the trip ridden last allows changing to (see [`Timetable::get_trip_transfer_time`])
+    fn earliest_trip(
+        &self,
+        route: T::Route,
+        stop: T::Stop,
+        tau: Tau,
+        k: K,
+    ) -> Result<Option<T::Trip>, Error> {
+        earliest_trip(self.timetable, route, stop, tau, self.last_transit(stop, k))
+    }
+
+    /// The transit leg ridden last to get to `stop` in round `k`, before walking there if the
+    /// label of `stop` is a footpath
+    fn last_transit(&self, stop: T::Stop, k: K) -> Option<Leg<T::Route, T::Trip, T::Stop>> {
+        let (round, label) = self.labels.get(stop, k)?;
+        let leg = match label.leg? {
+            Leg::Walk { from, .. } => self.labels.get(from, round)?.1.leg?,
+            leg => leg,
+        };
 
-            plan.push((route, stop));
-            parent = stop;
+        matches!(leg, Leg::Transit { .. }).then_some(leg)
+    }
+
+    /// Stores `label` for `stop` in round `k` if it improves on τ_k(stop) and arrives before
+    /// [`Search::arrival_bound`] (local and target pruning), and returns whether it did
+    fn improve(
+        &mut self,
+        stop: T::Stop,
+        k: K,
+        label: Label<T::Route, T::Trip, T::Stop>,
+        improved_target: &mut Vec<K>,
+    ) -> bool {
+        if label.arrival >= self.labels.arrival(stop, k).min(self.arrival_bound(k)) {
+            return false;
         }
 
-        if !plan.is_empty() && parent == ps {
-            plan.reverse();
-            plans.push(plan)
+        let target_arrival = self.target_arrival(k);
+
+        self.labels.insert(stop, k, label);
+        if self
+            .egress(stop)
+            .is_some_and(|egress| label.arrival.saturating_add(egress) < target_arrival)
+        {
+            improved_target.push(k);
+        }
+
+        true
+    }
+
+    /// Rebuilds the journey found in round `k` to `pt`, by following the legs back to the source
+    fn reconstruct_journey(
+        &self,
+        pt: T::Stop,
+        tau: Tau,
+        k: K,
+    ) -> Option<Journey<T::Route, T::Trip, T::Stop>> {
+        let mut legs = Vec::with_capacity(k);
+        let mut parent = pt;
+        let mut inner_k = k;
+
+        // We never reached target if there is no label. This is possible if ps and pt are nodes
+        // of a disjoint graph
+        loop {
+            let (round, label) = self.labels.get(parent, inner_k)?;
+            // reached the source
+            let Some(leg) = label.leg else { break };
+            legs.push(leg);
+
+            match leg {
+                Leg::Transit { board_stop, .. } => {
+                    parent = board_stop;
+                    inner_k = round.checked_sub(1)?;
+                }
+                // footpaths are walked in the same round as the trip before them
+                Leg::Walk { from, .. } => {
+                    parent = from;
+                    inner_k = round;
+                }
+                // walked to a source
+                Leg::Access { .. } => break,
+                Leg::Egress { .. } => return None,
+            }
         }
+
+        let journey = Journey {
+            legs: legs.into_iter().rev().collect(),
+            departure: tau,
+            arrival: self.labels.arrival(pt, k),
+        };
+
+        // no trips were taken
+        (journey.trips() > 0).then_some(journey)
     }
 
-    plans
+    /// Rebuilds the journey found in round `k` to the best target, walking to the destination
+    fn reconstruct_egress_journey(
+        &self,
+        tau: Tau,
+        k: K,
+    ) -> Option<Journey<T::Route, T::Trip, T::Stop>> {
+        let (pt, egress) = self.best_target(k)?;
+        let mut journey = self.reconstruct_journey(pt, tau, k)?;
+
+        journey.legs.push(Leg::Egress {
+            from: pt,
+            duration: egress,
+        });
+        journey.arrival = journey.arrival.saturating_add(egress);
+
+        Some(journey)
+    }
+}
+
+/// The earliest trip of `route` departing from `stop` after `tau` that can be changed to from
+/// `from`, the transit leg ridden last (see [`Timetable::get_trip_transfer_time`])
+pub(crate) fn earliest_trip<T: Timetable + ?Sized>(
+    tt: &T,
+    route: T::Route,
+    stop: T::Stop,
+    tau: Tau,
+    from: Option<Leg<T::Route, T::Trip, T::Stop>>,
+) -> Result<Option<T::Trip>, Error> {
+    let Some(mut trip) = tt.get_earliest_trip(route, tau, stop) else {
+        return Ok(None);
+    };
+    let Some(Leg::Transit {
+        route: from_route,
+        trip: from_trip,
+        alight_stop: from_stop,
+        alight_time,
+        ..
+    }) = from
+    else {
+        return Ok(Some(trip));
+    };
+
+    loop {
+        let departure = tt.try_get_departure_time(trip, stop)?;
+        let transfer_time =
+            tt.get_trip_transfer_time((from_route, from_trip, from_stop), (route, trip, stop));
+
+        let at = match transfer_time {
+            Some(time) if departure >= alight_time.saturating_add(time) => {
+                return Ok(Some(trip));
+            }
+            Some(time) => alight_time.saturating_add(time),
+            // forbidden, try the next trip
+            None => match departure.checked_add(1) {
+                Some(at) => at,
+                None => return Ok(None),
+            },
+        };
+
+        trip = match tt.get_earliest_trip(route, at, stop) {
+            Some(trip) => trip,
+            None => return Ok(None),
+        };
+    }
 }
 
 /// Raptor works on a structure called Timetable, which models a route based networks like a metro system's timetable
@@ -64,16 +733,19 @@ pub trait Timetable {
     type Route: Ord + Copy + Debug;
     type Trip: Copy + Debug;
 
-    // TODO: replace vec with cow or iter
-    fn get_routes_serving_stop(&self, stop: Self::Stop) -> Vec<Self::Route>;
+    fn get_routes_serving_stop(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Route> + '_;
     fn get_earlier_stop(
         &self,
         route: Self::Route,
         left: Self::Stop,
         right: Self::Stop,
     ) -> Self::Stop;
-    // TODO: replace vec with cow or iter
-    fn get_stops_after(&self, route: Self::Route, stop: Self::Stop) -> Vec<Self::Stop>;
+    /// The stops of `route` from `stop` to the last stop of the route, `stop` included
+    fn get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_;
     fn get_earliest_trip(
         &self,
         route: Self::Route,
@@ -82,113 +754,617 @@ pub trait Timetable {
     ) -> Option<Self::Trip>;
     fn get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau;
     fn get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> Tau;
-    // TODO: replace vec with cow or iter
-    fn get_footpaths_from(&self, stop: Self::Stop) -> Vec<Self::Stop>;
+    fn get_footpaths_from(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_;
     fn get_transfer_time(&self, from: Self::Stop, to: Self::Stop) -> Tau {
         let (_, _) = (from, to);
         1
     }
+    /// Minimum time between alighting from a trip at `stop` and boarding another one there
+    fn get_min_change_time(&self, stop: Self::Stop) -> Tau {
+        let _ = stop;
+        0
+    }
+    /// Minimum time between alighting from a trip at a stop, given as `from` (route, trip, stop),
+    /// and departing with another trip from a stop, given as `to`, or None if that transfer is
+    /// forbidden. The footpath between the stops, when they differ, is walked all the same. The
+    /// default allows every transfer, after [`Timetable::get_min_change_time`] when staying at the
+    /// same stop.
+    fn get_trip_transfer_time(
+        &self,
+        from: (Self::Route, Self::Trip, Self::Stop),
+        to: (Self::Route, Self::Trip, Self::Stop),
+    ) -> Option<Tau> {
+        if from.2 == to.2 {
+            Some(self.get_min_change_time(to.2))
+        } else {
+            Some(0)
+        }
+    }
+
+    // Fallible variants of the methods above, used by the search. Timetables that can be given
+    // bad input override these and report an error, the defaults can't fail.
+
+    /// Checks that `stop` is a stop of the timetable
+    fn check_stop(&self, stop: Self::Stop) -> Result<(), Error> {
+        let _ = stop;
+        Ok(())
+    }
+    fn try_get_earlier_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Result<Self::Stop, Error> {
+        Ok(self.get_earlier_stop(route, left, right))
+    }
+    fn try_get_stops_after(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        Ok(self.get_stops_after(route, stop))
+    }
+    fn try_get_arrival_time(&self, trip: Self::Trip, stop: Self::Stop) -> Result<Tau, Error> {
+        Ok(self.get_arrival_time(trip, stop))
+    }
+    fn try_get_departure_time(&self, trip: Self::Trip, stop: Self::Stop) -> Result<Tau, Error> {
+        Ok(self.get_departure_time(trip, stop))
+    }
 
+    /// Panics where [`Timetable::try_raptor`] returns an error
     fn raptor(
         &self,
         transfers: usize,
         tau: usize,
         ps: Self::Stop,
         pt: Self::Stop,
-    ) -> Vec<Journey<Self::Route, Self::Stop>> {
-        // for (i, stop) earliest known arrival time at `stop` with at most `i` transfers
-        let mut best_arrival_per_k = BTreeMap::<(K, Self::Stop), Tau>::new();
-        let mut best_arrival = BTreeMap::<Self::Stop, Tau>::new();
+    ) -> Vec<Journey<Self::Route, Self::Trip, Self::Stop>> {
+        self.try_raptor(transfers, tau, ps, pt)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn try_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.try_query(&RaptorQuery::new(ps, pt, tau).with_max_rounds(transfers))
+    }
 
-        best_arrival_per_k.insert((0, ps), tau);
-        let mut board_detail_per_k: BoardingTree<Self::Route, Self::Stop> = BTreeMap::new();
+    /// Panics where [`Timetable::try_query`] returns an error
+    fn query(&self, query: &RaptorQuery<Self::Stop>) -> Vec<JourneyFor<Self>> {
+        self.try_query(query).unwrap_or_else(|err| panic!("{err}"))
+    }
 
-        let mut marked_stops = BTreeSet::<Self::Stop>::from([ps]);
+    /// [`Timetable::try_raptor`] with the options of `query`
+    fn try_query(&self, query: &RaptorQuery<Self::Stop>) -> Result<Vec<JourneyFor<Self>>, Error> {
+        let RaptorQuery {
+            source: ps,
+            target: pt,
+            departure: tau,
+            ..
+        } = *query;
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
 
-        #[allow(non_snake_case)]
-        // allowing weird naming to match with the paper
-        let mut Q = BTreeMap::<Self::Route, Self::Stop>::new();
+        let targets = [(pt, 0)];
+        let mut search = Search::new(self, query.max_rounds, &targets).limit(query);
 
-        for k in 1..=transfers {
-            Q.clear();
-            // find all routes that serve the marked stops, for evaluation in this round
-            for &marked_stop in &marked_stops {
-                for route in self.get_routes_serving_stop(marked_stop) {
-                    let p_dash = Q.entry(route).or_insert(marked_stop);
+        Ok(search
+            .run(ps, tau)?
+            .into_iter()
+            .filter_map(|k| search.reconstruct_journey(pt, tau, k))
+            .collect())
+    }
 
-                    *p_dash = self.get_earlier_stop(route, marked_stop, *p_dash);
-                }
-            }
+    /// Panics where [`Timetable::try_raptor_multi`] returns an error
+    fn raptor_multi(
+        &self,
+        transfers: usize,
+        tau: usize,
+        access: &[(Self::Stop, Tau)],
+        egress: &[(Self::Stop, Tau)],
+    ) -> Vec<JourneyFor<Self>> {
+        self.try_raptor_multi(transfers, tau, access, egress)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Multi-source, multi-target RAPTOR, from an origin and to a destination that aren't stops.
+    ///
+    /// Leaving the origin at `tau`, journeys walk to one of the `access` stops, given with their
+    /// walking time, and walk to the destination from one of the `egress` stops. These walks are
+    /// the first and last legs of the journeys, and the arrival is at the destination.
+    fn try_raptor_multi(
+        &self,
+        transfers: usize,
+        tau: usize,
+        access: &[(Self::Stop, Tau)],
+        egress: &[(Self::Stop, Tau)],
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        for &(stop, _) in access.iter().chain(egress) {
+            self.check_stop(stop)?;
+        }
+
+        let mut search = Search::new(self, transfers, egress);
+        let sources = access.iter().map(|&(stop, duration)| {
+            let label = Label {
+                arrival: tau.saturating_add(duration),
+                leg: Some(Leg::Access { to: stop, duration }),
+            };
+            (stop, label)
+        });
+
+        Ok(search
+            .run_from(sources)?
+            .into_iter()
+            .filter_map(|k| search.reconstruct_egress_journey(tau, k))
+            .collect())
+    }
+
+    /// Panics where [`Timetable::try_range_raptor`] returns an error
+    fn range_raptor(
+        &self,
+        transfers: usize,
+        departures: RangeInclusive<Tau>,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Vec<Journey<Self::Route, Self::Trip, Self::Stop>> {
+        self.try_range_raptor(transfers, departures, ps, pt)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Range RAPTOR (rRAPTOR), finds all the Pareto-optimal journeys (later departure, earlier
+    /// arrival, fewer trips) departing from `ps` within `departures`.
+    ///
+    /// Runs a RAPTOR search for every departure of a trip from `ps` in the window, from the
+    /// latest to the earliest, reusing the labels of the later departures as upper bounds for the
+    /// earlier ones. A journey is only reported for a departure if it improves on everything found
+    /// for later departures. Journeys are sorted by departure, then by number of trips.
+    fn try_range_raptor(
+        &self,
+        transfers: usize,
+        departures: RangeInclusive<Tau>,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
+
+        let mut taus = BTreeSet::new();
 
-            marked_stops.clear();
-
-            // scanning each route
-            for (&route, &p) in Q.iter() {
-                let mut current_trip: Option<Self::Trip> = None;
-                let mut boarding_stop = p;
-
-                for pi in self.get_stops_after(route, p) {
-                    if let Some(arr) = current_trip.map(|trip| self.get_arrival_time(trip, pi)) {
-                        let best_arrival_to_target = best_arrival.get(&pt).unwrap_or(&Tau::MAX);
-                        let best_arrival_to_pi = best_arrival.get(&pi).unwrap_or(&Tau::MAX);
-                        let time_to_beat = *best_arrival_to_pi.min(best_arrival_to_target);
-
-                        if arr < time_to_beat {
-                            board_detail_per_k.insert((k, pi), (boarding_stop, route));
-                            best_arrival_per_k.insert((k, pi), arr);
-                            best_arrival.insert(pi, arr);
-                            marked_stops.insert(pi);
-                        }
-                    }
-
-                    let t_prev_pi = *best_arrival_per_k.get(&(k - 1, pi)).unwrap_or(&Tau::MAX);
-                    if t_prev_pi
-                        <= current_trip
-                            .map(|trip| self.get_departure_time(trip, pi))
-                            .unwrap_or(Tau::MAX)
-                    {
-                        current_trip = self.get_earliest_trip(route, t_prev_pi, pi);
-                        boarding_stop = pi;
-                    }
+        for route in self.get_routes_serving_stop(ps) {
+            let mut at = *departures.start();
+
+            while let Some(trip) = self.get_earliest_trip(route, at, ps) {
+                let departure = self.try_get_departure_time(trip, ps)?;
+                if !departures.contains(&departure) {
+                    break;
                 }
-            }
 
-            // look at footpaths, and mark the stops reachable
-            let mut more_marked_stops = Vec::new();
-            for &stop in &marked_stops {
-                for &p_dash in &self.get_footpaths_from(stop) {
-                    let tau = best_arrival_per_k
-                        .get(&(k, p_dash))
-                        .copied()
-                        .unwrap_or(Tau::MAX)
-                        .min(
-                            best_arrival_per_k
-                                .get(&(k, stop))
-                                .copied()
-                                .unwrap_or(Tau::MAX)
-                                + self.get_transfer_time(stop, p_dash),
-                        );
-                    best_arrival_per_k.insert((k, p_dash), tau);
-                    more_marked_stops.push(p_dash);
+                taus.insert(departure);
+                match departure.checked_add(1) {
+                    Some(next) => at = next,
+                    None => break,
                 }
             }
+        }
 
-            marked_stops.extend(&more_marked_stops);
+        let targets = [(pt, 0)];
+        let mut search = Search::new(self, transfers, &targets);
+        let mut journeys = Vec::new();
 
-            if marked_stops.is_empty() {
-                break;
+        for tau in taus.into_iter().rev() {
+            for k in search.run(ps, tau)? {
+                journeys.extend(search.reconstruct_journey(pt, tau, k));
             }
         }
 
-        let plans = reconstruct_journey(&board_detail_per_k, ps, pt, transfers);
+        journeys.sort_by_key(|journey| (journey.departure, journey.trips()));
+        Ok(journeys)
+    }
+
+    /// Panics where [`Timetable::try_earliest_arrivals`] returns an error
+    fn earliest_arrivals(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+    ) -> BTreeMap<Self::Stop, (Tau, K)> {
+        self.try_earliest_arrivals(transfers, tau, ps)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// One-to-all query, finds the earliest arrival at every stop reachable from `ps` departing
+    /// at `tau`, along with the number of trips taken to get there. The source is included with
+    /// no trips.
+    fn try_earliest_arrivals(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+    ) -> Result<BTreeMap<Self::Stop, (Tau, K)>, Error> {
+        self.check_stop(ps)?;
+
+        let mut search = Search::new(self, transfers, &[]);
+        search.run(ps, tau)?;
 
-        plans
+        Ok(search
+            .labels
+            .best()
+            .map(|(stop, k, label)| (stop, (label.arrival, k)))
+            .collect())
+    }
+
+    /// Panics where [`Timetable::try_isochrone`] returns an error
+    fn isochrone(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        duration: Tau,
+    ) -> BTreeSet<Self::Stop> {
+        self.try_isochrone(transfers, tau, ps, duration)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Stops reachable from `ps` departing at `tau` within `duration`, which is the set of stops
+    /// inside the isochrone of that duration
+    fn try_isochrone(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        duration: Tau,
+    ) -> Result<BTreeSet<Self::Stop>, Error> {
+        Ok(self
+            .try_earliest_arrivals(transfers, tau, ps)?
             .into_iter()
-            .map(|plan| {
-                let arrival = *best_arrival_per_k.get(&(plan.len(), pt)).unwrap();
+            .filter(|&(_, (arrival, _))| arrival - tau <= duration)
+            .map(|(stop, _)| stop)
+            .collect())
+    }
+
+    /// Panics where [`Timetable::try_mc_raptor`] returns an error
+    fn mc_raptor<C: mc::Criterion<Self>>(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+        criterion: &C,
+    ) -> Vec<mc::McJourneyFor<Self, C>> {
+        self.try_mc_raptor(transfers, tau, ps, pt, criterion)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// McRAPTOR, finds all the journeys from `ps` to `pt` that are Pareto-optimal with respect to
+    /// arrival time, number of trips and the given `criterion`. See [`mc::Criterion`] for the
+    /// criteria provided by the crate, tuples of criteria are optimised together.
+    fn try_mc_raptor<C: mc::Criterion<Self>>(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+        criterion: &C,
+    ) -> Result<Vec<mc::McJourneyFor<Self, C>>, Error> {
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
+
+        mc::mc_raptor(self, transfers, tau, ps, pt, criterion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::{Criterion, Fare, Ride, VehicleChanges, WalkingTime};
+
+    /// A route from a stop to another one, given as (from, to, [(departure, arrival)]) with its
+    /// trips sorted
+    type Line = (usize, usize, &'static [(Tau, Tau)]);
+
+    /// Lines, and footpaths given as (from, to, duration)
+    struct Network {
+        routes: Vec<Line>,
+        footpaths: Vec<(usize, usize, Tau)>,
+    }
 
-                Journey { plan, arrival }
+    // stops
+    const S: usize = 0;
+    const M: usize = 1;
+    const T: usize = 2;
+    const N: usize = 3;
+
+    impl Network {
+        fn new(routes: &[Line]) -> Self {
+            Self {
+                routes: routes.to_vec(),
+                footpaths: Vec::new(),
+            }
+        }
+
+        /// Time of `trip` at `stop`
+        fn time(&self, (route, trip): (usize, usize), stop: usize) -> Tau {
+            let (from, _, trips) = &self.routes[route];
+            let (departure, arrival) = trips[trip];
+            if stop == *from { departure } else { arrival }
+        }
+    }
+
+    impl Timetable for Network {
+        type Stop = usize;
+        type Route = usize;
+        // route, and index of the trip in the route
+        type Trip = (usize, usize);
+
+        fn get_routes_serving_stop(&self, stop: usize) -> impl Iterator<Item = usize> + '_ {
+            (0..self.routes.len()).filter(move |&route| {
+                let (from, to, _) = self.routes[route];
+                stop == from || stop == to
             })
+        }
+
+        fn get_earlier_stop(&self, route: usize, left: usize, right: usize) -> usize {
+            if right == self.routes[route].0 {
+                right
+            } else {
+                left
+            }
+        }
+
+        fn get_stops_after(&self, route: usize, stop: usize) -> impl Iterator<Item = usize> + '_ {
+            let (from, to, _) = self.routes[route];
+            let stops = if stop == from {
+                vec![from, to]
+            } else {
+                vec![to]
+            };
+            stops.into_iter()
+        }
+
+        fn get_earliest_trip(&self, route: usize, at: Tau, stop: usize) -> Option<(usize, usize)> {
+            (0..self.routes[route].2.len())
+                .find(|&trip| self.time((route, trip), stop) >= at)
+                .map(|trip| (route, trip))
+        }
+
+        fn get_arrival_time(&self, trip: (usize, usize), stop: usize) -> Tau {
+            self.time(trip, stop)
+        }
+
+        fn get_departure_time(&self, trip: (usize, usize), stop: usize) -> Tau {
+            self.time(trip, stop)
+        }
+
+        fn get_footpaths_from(&self, stop: usize) -> impl Iterator<Item = usize> + '_ {
+            self.footpaths
+                .iter()
+                .filter(move |&&(from, _, _)| from == stop)
+                .map(|&(_, to, _)| to)
+        }
+
+        fn get_transfer_time(&self, from: usize, to: usize) -> Tau {
+            self.footpaths
+                .iter()
+                .find(|&&(f, t, _)| (f, t) == (from, to))
+                .map_or(0, |&(_, _, duration)| duration)
+        }
+    }
+
+    /// (departure, arrival, trips) of `journeys`
+    fn summary(journeys: &[Journey<usize, (usize, usize), usize>]) -> Vec<(Tau, Tau, usize)> {
+        journeys
+            .iter()
+            .map(|journey| (journey.departure, journey.arrival, journey.trips()))
             .collect()
     }
+
+    #[test]
+    fn range_raptor_reports_departures_improving_on_later_ones() {
+        let network = Network::new(&[
+            // slow line
+            (S, T, &[(100, 1000), (300, 1200)]),
+            // express
+            (S, T, &[(200, 500)]),
+            // changing at M
+            (S, M, &[(250, 300)]),
+            (M, T, &[(350, 450)]),
+        ]);
+
+        // leaving at 100 only reaches T as early as leaving at 200 does, and leaving at 200 with
+        // a change as early as leaving at 250 does: the labels of the later departures rule them out
+        assert_eq!(
+            summary(&network.range_raptor(3, 0..=400, S, T)),
+            [(200, 500, 1), (250, 450, 2), (300, 1200, 1)]
+        );
+        // the trips of departures left out of the range are still ridden
+        assert_eq!(
+            summary(&network.range_raptor(3, 0..=220, S, T)),
+            [(200, 500, 1), (200, 450, 2)]
+        );
+        // on its own, leaving at 100 does reach T
+        assert_eq!(
+            summary(&network.raptor(3, 100, S, T)),
+            [(100, 500, 1), (100, 450, 2)]
+        );
+    }
+
+    #[test]
+    fn range_raptor_stops_at_the_last_departure() {
+        let network = Network::new(&[(S, T, &[(100, 500), (Tau::MAX, Tau::MAX)])]);
+
+        assert_eq!(
+            summary(&network.range_raptor(3, 0..=Tau::MAX, S, T)),
+            [(100, 500, 1)]
+        );
+    }
+
+    #[test]
+    fn query_limits_include_their_bound() {
+        let network = Network::new(&[
+            (S, T, &[(100, 500)]),
+            (S, M, &[(100, 200)]),
+            (M, T, &[(300, 450)]),
+        ]);
+        let query = RaptorQuery::new(S, T, 0);
+
+        assert_eq!(
+            summary(&network.query(&query.with_max_arrival(500))),
+            [(0, 500, 1), (0, 450, 2)]
+        );
+        assert_eq!(
+            summary(&network.query(&query.with_max_arrival(499))),
+            [(0, 450, 2)]
+        );
+        assert_eq!(
+            summary(&network.query(&query.with_max_duration(450))),
+            [(0, 450, 2)]
+        );
+        assert!(network.query(&query.with_max_duration(449)).is_empty());
+        // the tighter of the two limits applies
+        assert!(
+            network
+                .query(&query.with_max_duration(500).with_max_arrival(449))
+                .is_empty()
+        );
+        assert_eq!(
+            summary(&network.query(&query.with_max_rounds(1))),
+            [(0, 500, 1)]
+        );
+    }
+
+    #[test]
+    fn raptor_multi_walks_to_and_from_the_best_stops() {
+        let network = Network::new(&[(S, T, &[(400, 1000)]), (M, N, &[(200, 900)])]);
+
+        let journeys = network.raptor_multi(3, 0, &[(S, 300), (M, 100)], &[(T, 200), (N, 50)]);
+        assert_eq!(journeys.len(), 1);
+        assert_eq!((journeys[0].departure, journeys[0].arrival), (0, 950));
+        assert_eq!(
+            journeys[0].legs,
+            [
+                Leg::Access {
+                    to: M,
+                    duration: 100
+                },
+                Leg::Transit {
+                    route: 1,
+                    trip: (1, 0),
+                    board_stop: M,
+                    board_time: 200,
+                    alight_stop: N,
+                    alight_time: 900,
+                },
+                Leg::Egress {
+                    from: N,
+                    duration: 50
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn earliest_arrivals_and_isochrones_from_a_stop() {
+        let mut network = Network::new(&[
+            (S, M, &[(100, 200)]),
+            (M, T, &[(300, 450)]),
+            (S, N, &[(100, 900)]),
+        ]);
+        network.footpaths.push((M, N, 150));
+
+        // walking from M to N beats riding to N
+        let arrivals = network.earliest_arrivals(3, 50, S);
+        assert_eq!(
+            arrivals.into_iter().collect::<Vec<_>>(),
+            [(S, (50, 0)), (M, (200, 1)), (T, (450, 2)), (N, (350, 1))]
+        );
+        let arrivals = network.earliest_arrivals(1, 50, S);
+        assert_eq!(arrivals.keys().copied().collect::<Vec<_>>(), [S, M, N]);
+
+        // travel times from 50 are 0 to S, 150 to M, 300 to N and 400 to T
+        assert_eq!(
+            network.isochrone(3, 50, S, 400),
+            BTreeSet::from([S, M, T, N])
+        );
+        assert_eq!(network.isochrone(3, 50, S, 300), BTreeSet::from([S, M, N]));
+        assert_eq!(network.isochrone(3, 50, S, 299), BTreeSet::from([S, M]));
+    }
+
+    #[test]
+    fn mc_raptor_keeps_pareto_optimal_journeys() {
+        let mut network = Network::new(&[
+            (S, M, &[(100, 200)]),
+            (N, T, &[(550, 700)]),
+            (S, T, &[(100, 900)]),
+            (M, T, &[(750, 800)]),
+            (M, T, &[(600, 850)]),
+        ]);
+        network.footpaths.push((M, N, 300));
+
+        // arriving at 850 with as much walking and as many trips as arriving at 800 is dominated
+        let journeys = network.mc_raptor(3, 0, S, T, &WalkingTime);
+        let mut found = journeys
+            .iter()
+            .map(|mc| (mc.journey.arrival, mc.journey.trips(), mc.criteria))
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, [(700, 2, 300), (800, 2, 0), (900, 1, 0)]);
+
+        // without walking time, walking to N is just faster
+        let journeys = network.mc_raptor(3, 0, S, T, &());
+        let mut found = journeys
+            .iter()
+            .map(|mc| (mc.journey.arrival, mc.journey.trips()))
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, [(700, 2), (900, 1)]);
+    }
+
+    #[test]
+    fn vehicle_changes_count_changes_of_route() {
+        let ride = |route| Ride {
+            route,
+            trip: (route, 0),
+            from: S,
+            to: T,
+            departure: 0,
+            arrival: 0,
+        };
+        let ride_on =
+            |value, route| Criterion::<Network>::ride(&VehicleChanges, value, &ride(route));
+        let dominates = |a, b| Criterion::<Network>::dominates(&VehicleChanges, &a, &b);
+
+        let value = ride_on(Criterion::<Network>::initial(&VehicleChanges), 1);
+        assert_eq!(value, (0, Some(1)));
+        // another trip of the same route isn't a change
+        assert_eq!(ride_on(value, 1), (0, Some(1)));
+        assert_eq!(ride_on(value, 2), (1, Some(2)));
+
+        assert!(dominates((0, Some(1)), (1, Some(0))));
+        assert!(dominates((1, Some(0)), (1, Some(0))));
+        // either one can stay on its route without a change
+        assert!(!dominates((1, Some(0)), (1, Some(1))));
+        assert!(!dominates((1, Some(1)), (1, Some(0))));
+    }
+
+    #[test]
+    fn criteria_saturate() {
+        let fare = Fare(|_: &Ride<usize, (usize, usize), usize>| u32::MAX);
+        let ride = Ride {
+            route: 0,
+            trip: (0, 0),
+            from: S,
+            to: M,
+            departure: 100,
+            arrival: 200,
+        };
+        let value = Criterion::<Network>::ride(&fare, u32::MAX, &ride);
+        assert_eq!(value, u32::MAX);
+
+        let value = Criterion::<Network>::walk(&WalkingTime, Tau::MAX, M, N, Tau::MAX);
+        assert_eq!(value, Tau::MAX);
+    }
 }
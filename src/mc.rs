@@ -0,0 +1,448 @@
+//! McRAPTOR, the multi-criteria variant of RAPTOR.
+//!
+//! Instead of a single arrival time per stop and round, McRAPTOR keeps a bag of Pareto-optimal
+//! labels. Arrival time and number of trips are always optimised, any further criteria are
+//! plugged in through [`Criterion`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+use crate::{Error, Journey, K, Leg, Tau, Timetable, earliest_trip};
+
+/// A trip ridden from one stop to another
+#[derive(Debug, Clone, Copy)]
+pub struct Ride<Route, Trip, Stop> {
+    pub route: Route,
+    pub trip: Trip,
+    pub from: Stop,
+    pub to: Stop,
+    pub departure: Tau,
+    pub arrival: Tau,
+}
+
+/// A criterion optimised by McRAPTOR, in addition to arrival time and number of trips.
+///
+/// Values only ever grow along a journey (a ride or a walk never makes a label better), which is
+/// what allows McRAPTOR to prune labels against the ones already found at the target.
+pub trait Criterion<T: Timetable + ?Sized> {
+    type Value: Copy + Debug + PartialOrd;
+
+    /// Value at the source, before anything was ridden or walked
+    fn initial(&self) -> Self::Value;
+
+    fn ride(&self, value: Self::Value, ride: &Ride<T::Route, T::Trip, T::Stop>) -> Self::Value {
+        let _ = ride;
+        value
+    }
+
+    fn walk(&self, value: Self::Value, from: T::Stop, to: T::Stop, duration: Tau) -> Self::Value {
+        let (_, _, _) = (from, to, duration);
+        value
+    }
+
+    /// Whether a label with value `a` is at least as good as one with value `b`
+    fn dominates(&self, a: &Self::Value, b: &Self::Value) -> bool {
+        a <= b
+    }
+}
+
+/// No additional criteria, McRAPTOR degenerates to RAPTOR
+impl<T: Timetable + ?Sized> Criterion<T> for () {
+    type Value = ();
+
+    fn initial(&self) -> Self::Value {}
+}
+
+/// Total time spent walking on footpaths
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkingTime;
+
+impl<T: Timetable + ?Sized> Criterion<T> for WalkingTime {
+    type Value = Tau;
+
+    fn initial(&self) -> Self::Value {
+        0
+    }
+
+    fn walk(&self, value: Self::Value, _: T::Stop, _: T::Stop, duration: Tau) -> Self::Value {
+        value.saturating_add(duration)
+    }
+}
+
+/// Number of times a different route was boarded after the first ride. Unlike the number of
+/// trips, staying on the same route across two trips does not count as a change.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VehicleChanges;
+
+impl<T: Timetable + ?Sized> Criterion<T> for VehicleChanges {
+    // number of changes, and the last route ridden
+    type Value = (u32, Option<T::Route>);
+
+    fn initial(&self) -> Self::Value {
+        (0, None)
+    }
+
+    fn ride(
+        &self,
+        (changes, last): Self::Value,
+        ride: &Ride<T::Route, T::Trip, T::Stop>,
+    ) -> Self::Value {
+        match last {
+            Some(route) if route != ride.route => (changes + 1, Some(ride.route)),
+            _ => (changes, Some(ride.route)),
+        }
+    }
+
+    fn dominates(&self, a: &Self::Value, b: &Self::Value) -> bool {
+        // a label that already changed less than another is better even if the other one stays on
+        // its route for one more ride
+        a.0 < b.0 || a == b
+    }
+}
+
+/// Total fare, as the sum of the fares of every ride given by the closure
+#[derive(Debug, Clone, Copy)]
+pub struct Fare<F>(pub F);
+
+impl<T, F> Criterion<T> for Fare<F>
+where
+    T: Timetable + ?Sized,
+    F: Fn(&Ride<T::Route, T::Trip, T::Stop>) -> u32,
+{
+    type Value = u32;
+
+    fn initial(&self) -> Self::Value {
+        0
+    }
+
+    fn ride(&self, value: Self::Value, ride: &Ride<T::Route, T::Trip, T::Stop>) -> Self::Value {
+        value.saturating_add((self.0)(ride))
+    }
+}
+
+macro_rules! impl_criterion_for_tuple {
+    ($($name:ident $idx:tt),+) => {
+        impl<T: Timetable + ?Sized, $($name: Criterion<T>),+> Criterion<T> for ($($name,)+) {
+            type Value = ($($name::Value,)+);
+
+            fn initial(&self) -> Self::Value {
+                ($(self.$idx.initial(),)+)
+            }
+
+            fn ride(
+                &self,
+                value: Self::Value,
+                ride: &Ride<T::Route, T::Trip, T::Stop>,
+            ) -> Self::Value {
+                ($(self.$idx.ride(value.$idx, ride),)+)
+            }
+
+            fn walk(
+                &self,
+                value: Self::Value,
+                from: T::Stop,
+                to: T::Stop,
+                duration: Tau,
+            ) -> Self::Value {
+                ($(self.$idx.walk(value.$idx, from, to, duration),)+)
+            }
+
+            fn dominates(&self, a: &Self::Value, b: &Self::Value) -> bool {
+                $(self.$idx.dominates(&a.$idx, &b.$idx))&&+
+            }
+        }
+    };
+}
+
+impl_criterion_for_tuple!(A 0);
+impl_criterion_for_tuple!(A 0, B 1);
+impl_criterion_for_tuple!(A 0, B 1, C 2);
+impl_criterion_for_tuple!(A 0, B 1, C 2, D 3);
+
+/// A Pareto-optimal journey found by McRAPTOR, along with the value of its criteria
+#[derive(Debug, Clone)]
+pub struct McJourney<Route, Trip, Stop, Value> {
+    pub journey: Journey<Route, Trip, Stop>,
+    pub criteria: Value,
+}
+
+/// [`McJourney`] found in timetable `T` while optimising `C`
+pub type McJourneyFor<T, C> = McJourney<
+    <T as Timetable>::Route,
+    <T as Timetable>::Trip,
+    <T as Timetable>::Stop,
+    <C as Criterion<T>>::Value,
+>;
+
+#[derive(Debug, Clone, Copy)]
+struct McLabel<Route, Trip, Stop, Value> {
+    arrival: Tau,
+    value: Value,
+    // None for the source
+    leg: Option<Leg<Route, Trip, Stop>>,
+    parent: Option<usize>,
+}
+
+type McLabelFor<T, C> = McLabel<
+    <T as Timetable>::Route,
+    <T as Timetable>::Trip,
+    <T as Timetable>::Stop,
+    <C as Criterion<T>>::Value,
+>;
+
+/// A label riding a trip of the route being scanned
+struct RouteLabel<Trip, Stop> {
+    trip: Trip,
+    board_stop: Stop,
+    departure: Tau,
+    // label at the boarding stop
+    base: usize,
+}
+
+struct McSearch<'tt, T: Timetable + ?Sized, C: Criterion<T>> {
+    timetable: &'tt T,
+    criterion: &'tt C,
+    pt: T::Stop,
+    labels: Vec<McLabelFor<T, C>>,
+    // B_k(p), only holding the labels found in round k
+    bags: BTreeMap<(T::Stop, K), Vec<usize>>,
+}
+
+impl<'tt, T: Timetable + ?Sized, C: Criterion<T>> McSearch<'tt, T, C> {
+    fn dominates(&self, a: usize, arrival: Tau, value: &C::Value) -> bool {
+        let a = &self.labels[a];
+        a.arrival <= arrival && self.criterion.dominates(&a.value, value)
+    }
+
+    /// Whether a label is dominated by one with at most `k` trips, at `stop` or at the target
+    fn is_dominated(&self, stop: T::Stop, k: K, arrival: Tau, value: &C::Value) -> bool {
+        [stop, self.pt].into_iter().any(|stop| {
+            self.bags
+                .range((stop, 0)..=(stop, k))
+                .flat_map(|(_, bag)| bag)
+                .any(|&label| self.dominates(label, arrival, value))
+        })
+    }
+
+    /// Adds a label to B_k(stop) unless it is dominated, returns whether it was added
+    fn insert(&mut self, stop: T::Stop, k: K, label: McLabelFor<T, C>) -> bool {
+        if self.is_dominated(stop, k, label.arrival, &label.value) {
+            return false;
+        }
+
+        let idx = self.labels.len();
+        self.labels.push(label);
+
+        let mut bag = self.bags.remove(&(stop, k)).unwrap_or_default();
+        bag.retain(|&other| {
+            let other = &self.labels[other];
+            !self.dominates(idx, other.arrival, &other.value)
+        });
+        bag.push(idx);
+        self.bags.insert((stop, k), bag);
+
+        true
+    }
+
+    fn run(&mut self, transfers: K, tau: Tau, ps: T::Stop) -> Result<(), Error> {
+        let tt = self.timetable;
+        let criterion = self.criterion;
+
+        self.insert(
+            ps,
+            0,
+            McLabel {
+                arrival: tau,
+                value: criterion.initial(),
+                leg: None,
+                parent: None,
+            },
+        );
+        let mut marked_stops = BTreeSet::from([ps]);
+
+        #[allow(non_snake_case)]
+        // allowing weird naming to match with the paper
+        let mut Q = BTreeMap::<T::Route, T::Stop>::new();
+
+        for k in 1..=transfers {
+            Q.clear();
+            for &marked_stop in &marked_stops {
+                for route in tt.get_routes_serving_stop(marked_stop) {
+                    let p_dash = Q.entry(route).or_insert(marked_stop);
+
+                    *p_dash = tt.try_get_earlier_stop(route, marked_stop, *p_dash)?;
+                }
+            }
+
+            marked_stops.clear();
+
+            for (&route, &p) in Q.iter() {
+                let mut route_bag: Vec<RouteLabel<T::Trip, T::Stop>> = Vec::new();
+
+                for pi in tt.try_get_stops_after(route, p)? {
+                    // ride every trip of the route bag to pi
+                    for rl in &route_bag {
+                        let ride = Ride {
+                            route,
+                            trip: rl.trip,
+                            from: rl.board_stop,
+                            to: pi,
+                            departure: rl.departure,
+                            arrival: tt.try_get_arrival_time(rl.trip, pi)?,
+                        };
+                        let label = McLabel {
+                            arrival: ride.arrival,
+                            value: criterion.ride(self.labels[rl.base].value, &ride),
+                            leg: Some(Leg::Transit {
+                                route,
+                                trip: rl.trip,
+                                board_stop: rl.board_stop,
+                                board_time: rl.departure,
+                                alight_stop: pi,
+                                alight_time: ride.arrival,
+                            }),
+                            parent: Some(rl.base),
+                        };
+
+                        if self.insert(pi, k, label) {
+                            marked_stops.insert(pi);
+                        }
+                    }
+
+                    // board with every label of the previous round
+                    let Some(bag) = self.bags.get(&(pi, k - 1)) else {
+                        continue;
+                    };
+
+                    for &base in bag {
+                        let base_label = &self.labels[base];
+                        let from = self.last_transit(base);
+                        let Some(trip) = earliest_trip(tt, route, pi, base_label.arrival, from)?
+                        else {
+                            continue;
+                        };
+                        let departure = tt.try_get_departure_time(trip, pi)?;
+
+                        let dominated = route_bag.iter().any(|rl| {
+                            rl.board_stop == pi
+                                && rl.departure <= departure
+                                && criterion
+                                    .dominates(&self.labels[rl.base].value, &base_label.value)
+                        });
+
+                        if !dominated {
+                            route_bag.push(RouteLabel {
+                                trip,
+                                board_stop: pi,
+                                departure,
+                                base,
+                            });
+                        }
+                    }
+                }
+            }
+
+            // footpaths from the labels found by riding in this round
+            let mut walks = Vec::new();
+            for &stop in &marked_stops {
+                for &label in self.bags.get(&(stop, k)).into_iter().flatten() {
+                    let McLabel { arrival, value, .. } = self.labels[label];
+
+                    for p_dash in tt.get_footpaths_from(stop) {
+                        let duration = tt.get_transfer_time(stop, p_dash);
+                        walks.push((
+                            p_dash,
+                            McLabel {
+                                arrival: arrival.saturating_add(duration),
+                                value: criterion.walk(value, stop, p_dash, duration),
+                                leg: Some(Leg::Walk {
+                                    from: stop,
+                                    to: p_dash,
+                                    duration,
+                                }),
+                                parent: Some(label),
+                            },
+                        ));
+                    }
+                }
+            }
+
+            for (p_dash, label) in walks {
+                if self.insert(p_dash, k, label) {
+                    marked_stops.insert(p_dash);
+                }
+            }
+
+            if marked_stops.is_empty() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The transit leg ridden last to get to a label, before walking there if the label is a
+    /// footpath
+    fn last_transit(&self, label: usize) -> Option<Leg<T::Route, T::Trip, T::Stop>> {
+        let label = &self.labels[label];
+        let leg = match label.leg? {
+            Leg::Walk { .. } => self.labels[label.parent?].leg?,
+            leg => leg,
+        };
+
+        matches!(leg, Leg::Transit { .. }).then_some(leg)
+    }
+
+    fn reconstruct_journey(&self, label: usize, tau: Tau) -> Journey<T::Route, T::Trip, T::Stop> {
+        let arrival = self.labels[label].arrival;
+        let mut legs = Vec::new();
+        let mut current = Some(label);
+
+        while let Some(idx) = current {
+            let label = &self.labels[idx];
+            legs.extend(label.leg);
+            current = label.parent;
+        }
+
+        legs.reverse();
+        Journey {
+            legs,
+            departure: tau,
+            arrival,
+        }
+    }
+}
+
+/// Runs McRAPTOR, see [`Timetable::try_mc_raptor`]
+pub(crate) fn mc_raptor<T, C>(
+    timetable: &T,
+    transfers: K,
+    tau: Tau,
+    ps: T::Stop,
+    pt: T::Stop,
+    criterion: &C,
+) -> Result<Vec<McJourneyFor<T, C>>, Error>
+where
+    T: Timetable + ?Sized,
+    C: Criterion<T>,
+{
+    let mut search = McSearch {
+        timetable,
+        criterion,
+        pt,
+        labels: Vec::new(),
+        bags: BTreeMap::new(),
+    };
+
+    search.run(transfers, tau, ps)?;
+
+    Ok(search
+        .bags
+        .range((pt, 1)..=(pt, K::MAX))
+        .flat_map(|(_, bag)| bag)
+        .map(|&label| McJourney {
+            journey: search.reconstruct_journey(label, tau),
+            criteria: search.labels[label].value,
+        })
+        .collect())
+}
@@ -0,0 +1,94 @@
+//! Scanning the routes of a round in parallel, with the `rayon` feature.
+//!
+//! Within a round, routes only read the labels of the previous round, so the paper scans them on
+//! several cores at once. Each route of Q is scanned on the rayon thread pool against the labels as
+//! they were at the start of the round, and the arrivals found are then kept in the order of Q,
+//! exactly as the sequential scan does. Journeys are the same as those of [`Timetable::raptor`],
+//! whatever the number of threads.
+
+use rayon::prelude::*;
+
+use crate::{
+    Arrivals, Error, JourneyFor, K, Label, LabelStore, RouteQueue, Search, StopSet, Tau, Timetable,
+};
+
+/// A [`Timetable`] that can be shared between threads, whose queries can scan routes in parallel
+pub trait ParallelTimetable:
+    Timetable<Stop: Send + Sync, Route: Send + Sync, Trip: Send + Sync> + Sync
+{
+    /// [`Timetable::raptor`], scanning the routes of each round in parallel
+    fn par_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Vec<JourneyFor<Self>> {
+        self.try_par_raptor(transfers, tau, ps, pt)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// [`Timetable::try_raptor`], scanning the routes of each round in parallel
+    fn try_par_raptor(
+        &self,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
+
+        let targets = [(pt, 0)];
+        let mut search = Search::new(self, transfers, &targets);
+
+        Ok(search
+            .par_run(ps, tau)?
+            .into_iter()
+            .filter_map(|k| search.reconstruct_journey(pt, tau, k))
+            .collect())
+    }
+}
+
+impl<T> ParallelTimetable for T where
+    T: Timetable<Stop: Send + Sync, Route: Send + Sync, Trip: Send + Sync> + Sync + ?Sized
+{
+}
+
+impl<T, L, M, Q> Search<'_, T, L, M, Q>
+where
+    T: Timetable + ?Sized,
+    L: LabelStore<T::Route, T::Trip, T::Stop>,
+    M: StopSet<T::Stop>,
+    Q: RouteQueue<T::Route, T::Stop>,
+    T::Stop: Send,
+    T::Route: Send,
+    T::Trip: Send,
+    Self: Sync,
+{
+    /// [`Search::run`], scanning the routes of each round in parallel
+    fn par_run(&mut self, ps: T::Stop, tau: Tau) -> Result<Vec<K>, Error> {
+        let source = Label {
+            arrival: tau,
+            leg: None,
+        };
+
+        self.run_from_with([(ps, source)], Self::par_scan_routes)
+    }
+
+    /// [`Search::scan_routes`] on the rayon thread pool, keeping the arrivals in the order of Q
+    fn par_scan_routes(&self, k: K, arrivals: &mut Arrivals<T>) -> Result<(), Error> {
+        let queue: Vec<_> = self.queue.iter().collect();
+        let per_route = queue
+            .into_par_iter()
+            .map(|(route, p)| {
+                let mut route_arrivals = Vec::new();
+                self.scan_route(route, p, k, &mut route_arrivals)?;
+                Ok(route_arrivals)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        arrivals.extend(per_route.into_iter().flatten());
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{K, Tau};
+
+/// A RAPTOR query from `source` to `target`, along with the options of the search. Options are
+/// set with the `with_*` methods, and default to an unlimited search with target pruning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaptorQuery<Stop> {
+    pub(crate) source: Stop,
+    pub(crate) target: Stop,
+    pub(crate) departure: Tau,
+    pub(crate) max_rounds: K,
+    pub(crate) max_duration: Option<Tau>,
+    pub(crate) max_arrival: Option<Tau>,
+    pub(crate) target_pruning: bool,
+}
+
+impl<Stop> RaptorQuery<Stop> {
+    /// Departing from `source` at `departure` to `target`
+    pub fn new(source: Stop, target: Stop, departure: Tau) -> Self {
+        Self {
+            source,
+            target,
+            departure,
+            max_rounds: K::MAX,
+            max_duration: None,
+            max_arrival: None,
+            target_pruning: true,
+        }
+    }
+
+    /// Runs at most `rounds` rounds, so journeys take at most that many trips. The search stops
+    /// earlier once a round marks no stop.
+    pub fn with_max_rounds(mut self, rounds: K) -> Self {
+        self.max_rounds = rounds;
+        self
+    }
+
+    /// Drops every stop reached more than `duration` after the departure
+    pub fn with_max_duration(mut self, duration: Tau) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Drops every stop reached after `arrival`
+    pub fn with_max_arrival(mut self, arrival: Tau) -> Self {
+        self.max_arrival = Some(arrival);
+        self
+    }
+
+    /// Whether to drop the stops reached no earlier than the target (on by default). Rounds then
+    /// only mark stops that can still improve the arrival at the target, and the search ends as
+    /// soon as none is left. Turning it off labels every stop within the other limits.
+    pub fn with_target_pruning(mut self, target_pruning: bool) -> Self {
+        self.target_pruning = target_pruning;
+        self
+    }
+
+    /// Stops have to be reached before this time, following the maximum duration and arrival
+    pub(crate) fn arrival_limit(&self) -> Tau {
+        let duration_limit = self
+            .max_duration
+            .map(|duration| self.departure.saturating_add(duration));
+
+        [duration_limit, self.max_arrival]
+            .into_iter()
+            .flatten()
+            .min()
+            .map_or(Tau::MAX, |limit| limit.saturating_add(1))
+    }
+}
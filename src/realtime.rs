@@ -0,0 +1,120 @@
+//! Realtime updates of a [`GtfsTimetable`] from GTFS-Realtime feeds, with the `realtime` feature.
+//!
+//! A [`RealtimeTimetable`] keeps the timetable of the schedule, and publishes a snapshot of it for
+//! every feed it reads, with the trip updates of the feed applied to the runs of the service date:
+//!
+//! - stop time updates delay a run at a stop, and the delay carries over to the next stops
+//! - skipped stops are left out of the run
+//! - cancelled and deleted trips don't run
+//! - added (and new) trips run at the times of their stop time updates
+//!
+//! Snapshots are published atomically, a query keeps running on the snapshot it started with
+//! while newer ones come in. Every feed is taken as a full dataset replacing the previous one, and
+//! the updates of trips that don't run on the service date are left out.
+//!
+//! Feeds are read from bytes or from a file, decoding the messages of [`proto`].
+
+pub mod proto;
+
+use std::{fs, path::Path, sync::Arc};
+
+use arc_swap::ArcSwap;
+use prost::Message;
+
+use crate::Error;
+use crate::gtfs::GtfsTimetable;
+use proto::FeedMessage;
+use proto::feed_header::Incrementality;
+
+/// A [`GtfsTimetable`] kept up to date with GTFS-Realtime feeds
+pub struct RealtimeTimetable<'gtfs> {
+    schedule: GtfsTimetable<'gtfs>,
+    // POSIX time of the start of the service date
+    day_start: i64,
+    // snapshot of the schedule, published again when the updates are cleared
+    unchanged: Arc<GtfsTimetable<'gtfs>>,
+    snapshot: ArcSwap<GtfsTimetable<'gtfs>>,
+}
+
+impl<'gtfs> RealtimeTimetable<'gtfs> {
+    /// Panics where [`RealtimeTimetable::try_new`] returns an error
+    pub fn new(schedule: GtfsTimetable<'gtfs>) -> Self {
+        Self::try_new(schedule).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Starts from the `schedule` without realtime updates. Fails if it has no service date, or
+    /// its feed no agency with a known timezone, the times of the feeds being POSIX times.
+    pub fn try_new(schedule: GtfsTimetable<'gtfs>) -> Result<Self, Error> {
+        let day_start = schedule.service_day_start()?;
+        let unchanged = Arc::new(schedule.with_trip_updates([], day_start)?);
+        let snapshot = ArcSwap::new(Arc::clone(&unchanged));
+
+        Ok(Self {
+            schedule,
+            day_start,
+            unchanged,
+            snapshot,
+        })
+    }
+
+    /// The timetable without realtime updates
+    pub fn schedule(&self) -> &GtfsTimetable<'gtfs> {
+        &self.schedule
+    }
+
+    /// The latest snapshot. Run every query of a journey search on the same snapshot, newer ones
+    /// can renumber the routes and trips of realtime updates.
+    pub fn snapshot(&self) -> Arc<GtfsTimetable<'gtfs>> {
+        self.snapshot.load_full()
+    }
+
+    /// Applies the trip updates of `feed` to the schedule, and publishes the result as the latest
+    /// snapshot. Differential feeds aren't supported, nor are updates with times out of range:
+    /// the previous snapshot stays the latest one.
+    pub fn update(&self, feed: &FeedMessage) -> Result<(), Error> {
+        if feed.header.incrementality() == Incrementality::Differential {
+            return Err(Error::InvalidRealtime(
+                "differential feeds aren't supported".to_string(),
+            ));
+        }
+
+        let updates = feed
+            .entity
+            .iter()
+            .filter(|entity| !entity.is_deleted())
+            .filter_map(|entity| entity.trip_update.as_ref());
+        let timetable = self.schedule.with_trip_updates(updates, self.day_start)?;
+        self.snapshot.store(Arc::new(timetable));
+
+        Ok(())
+    }
+
+    /// [`RealtimeTimetable::update`] with a feed encoded in `bytes`
+    pub fn update_from_bytes(&self, bytes: &[u8]) -> Result<(), Error> {
+        self.update(&decode_feed(bytes)?)
+    }
+
+    /// [`RealtimeTimetable::update`] with a feed read from the file at `path`
+    pub fn update_from_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.update(&read_feed(path)?)
+    }
+
+    /// Drops the realtime updates, publishing the schedule as the latest snapshot
+    pub fn clear(&self) {
+        self.snapshot.store(Arc::clone(&self.unchanged));
+    }
+}
+
+/// Decodes a feed from its protobuf encoding
+pub fn decode_feed(bytes: &[u8]) -> Result<FeedMessage, Error> {
+    FeedMessage::decode(bytes).map_err(|err| Error::InvalidRealtime(err.to_string()))
+}
+
+/// Reads and decodes the feed of the file at `path`
+pub fn read_feed(path: impl AsRef<Path>) -> Result<FeedMessage, Error> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)
+        .map_err(|err| Error::InvalidRealtime(format!("can't read {}: {err}", path.display())))?;
+
+    decode_feed(&bytes)
+}
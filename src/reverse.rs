@@ -0,0 +1,358 @@
+//! Reverse RAPTOR, answering "arrive by" queries.
+//!
+//! The search runs backwards from the target with a deadline, scanning routes from their later
+//! stops to their earlier ones, and finds the latest departure from the source for every number of
+//! trips.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Error, Journey, JourneyFor, K, Label, LabelStore, Labels, Leg, Tau, Timetable};
+
+/// Reversed counterparts of the [`Timetable`] methods, needed to run RAPTOR backwards
+pub trait ReverseTimetable: Timetable {
+    /// The stops of `route` from `stop` back to the first stop of the route, `stop` included
+    fn get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> impl Iterator<Item = Self::Stop> + '_;
+    /// The latest trip of `route` arriving at `stop` no later than `at`
+    fn get_latest_trip(&self, route: Self::Route, at: Tau, stop: Self::Stop) -> Option<Self::Trip>;
+    /// The stops that have a footpath to `stop`
+    fn get_footpaths_to(&self, stop: Self::Stop) -> impl Iterator<Item = Self::Stop> + '_;
+
+    fn try_get_stops_before(
+        &self,
+        route: Self::Route,
+        stop: Self::Stop,
+    ) -> Result<impl Iterator<Item = Self::Stop> + '_, Error> {
+        Ok(self.get_stops_before(route, stop))
+    }
+
+    fn get_later_stop(
+        &self,
+        route: Self::Route,
+        left: Self::Stop,
+        right: Self::Stop,
+    ) -> Self::Stop {
+        if self.get_earlier_stop(route, left, right) == left {
+            right
+        } else {
+            left
+        }
+    }
+
+    /// Panics where [`ReverseTimetable::try_raptor_arrive_by`] returns an error
+    fn raptor_arrive_by(
+        &self,
+        transfers: usize,
+        deadline: Tau,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Vec<Journey<Self::Route, Self::Trip, Self::Stop>> {
+        self.try_raptor_arrive_by(transfers, deadline, ps, pt)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Finds the latest departures from `ps` that still reach `pt` by `deadline`, one journey per
+    /// number of trips, each one departing later than the ones with fewer trips.
+    fn try_raptor_arrive_by(
+        &self,
+        transfers: usize,
+        deadline: Tau,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
+
+        let mut search = ReverseSearch::new(self, ps);
+        search.run(transfers, deadline, pt)?;
+
+        Ok((1..=transfers)
+            .filter(|&k| {
+                search
+                    .labels
+                    .get(ps, k)
+                    .is_some_and(|(round, _)| round == k)
+            })
+            .filter_map(|k| search.reconstruct_journey(ps, pt, k))
+            .collect())
+    }
+}
+
+// Labels minimise their time, so the reverse search stores the departures flipped around to find
+// the latest ones
+fn flip(tau: Tau) -> Tau {
+    Tau::MAX - tau
+}
+
+struct ReverseSearch<'tt, T: ReverseTimetable + ?Sized> {
+    timetable: &'tt T,
+    ps: T::Stop,
+    // latest departure from every stop that still reaches the target, flipped
+    labels: Labels<T::Route, T::Trip, T::Stop>,
+}
+
+impl<'tt, T: ReverseTimetable + ?Sized> ReverseSearch<'tt, T> {
+    fn new(timetable: &'tt T, ps: T::Stop) -> Self {
+        Self {
+            timetable,
+            ps,
+            labels: Labels::new(),
+        }
+    }
+
+    /// Latest departure from `stop` with at most `k` trips, 0 when the target can't be reached
+    fn departure(&self, stop: T::Stop, k: K) -> Tau {
+        flip(self.labels.arrival(stop, k))
+    }
+
+    fn run(&mut self, transfers: K, deadline: Tau, pt: T::Stop) -> Result<(), Error> {
+        let tt = self.timetable;
+        let ps = self.ps;
+
+        self.labels.insert(
+            pt,
+            0,
+            Label {
+                arrival: flip(deadline),
+                leg: None,
+            },
+        );
+        let mut marked_stops = BTreeSet::from([pt]);
+
+        #[allow(non_snake_case)]
+        // allowing weird naming to match with the paper
+        let mut Q = BTreeMap::<T::Route, T::Stop>::new();
+
+        for k in 1..=transfers {
+            Q.clear();
+            // routes are scanned backwards, from the latest marked stop
+            for &marked_stop in &marked_stops {
+                for route in tt.get_routes_serving_stop(marked_stop) {
+                    let p_dash = Q.entry(route).or_insert(marked_stop);
+
+                    if tt.try_get_earlier_stop(route, marked_stop, *p_dash)? == *p_dash {
+                        *p_dash = marked_stop;
+                    }
+                }
+            }
+
+            marked_stops.clear();
+
+            for (&route, &p) in Q.iter() {
+                let mut current_trip: Option<T::Trip> = None;
+                let mut alighting_stop = p;
+                let mut alighting_time = 0;
+
+                for pi in tt.try_get_stops_before(route, p)? {
+                    // runs of the previous day reached the first stops before midnight, they
+                    // aren't ridden back there
+                    let departure =
+                        match current_trip.map(|trip| tt.try_get_departure_time(trip, pi)) {
+                            Some(Err(Error::TripNotAtStop { .. })) => {
+                                current_trip = None;
+                                None
+                            }
+                            departure => departure.transpose()?,
+                        };
+
+                    if let (Some(trip), Some(dep)) = (current_trip, departure) {
+                        let time_to_beat = self.departure(pi, k).max(self.departure(ps, k));
+
+                        if dep > time_to_beat {
+                            let label = Label {
+                                arrival: flip(dep),
+                                leg: Some(Leg::Transit {
+                                    route,
+                                    trip,
+                                    board_stop: pi,
+                                    board_time: dep,
+                                    alight_stop: alighting_stop,
+                                    alight_time: alighting_time,
+                                }),
+                            };
+                            self.labels.insert(pi, k, label);
+                            marked_stops.insert(pi);
+                        }
+                    }
+
+                    let t_prev_pi = self.departure(pi, k - 1);
+                    let can_alight = self.labels.get(pi, k - 1).is_some();
+                    let current_arrival = match current_trip {
+                        Some(trip) => tt.try_get_arrival_time(trip, pi)?,
+                        None => 0,
+                    };
+                    if can_alight && t_prev_pi >= current_arrival {
+                        let next = self.next_transit(pi, k - 1);
+                        let trip = latest_trip(tt, route, pi, t_prev_pi, next)?;
+
+                        // transfer rules can leave only earlier trips than the current one to
+                        // change from
+                        if let Some(trip) = trip {
+                            let arrival = tt.try_get_arrival_time(trip, pi)?;
+                            if arrival >= current_arrival {
+                                current_trip = Some(trip);
+                                alighting_stop = pi;
+                                alighting_time = arrival;
+                            }
+                        }
+                    }
+                }
+            }
+
+            // footpaths leading to the stops reached in this round
+            let mut walks = Vec::new();
+            for &stop in &marked_stops {
+                let departure_from_stop = self.departure(stop, k);
+
+                for p_dash in tt.get_footpaths_to(stop) {
+                    let duration = tt.get_transfer_time(p_dash, stop);
+                    walks.push((
+                        p_dash,
+                        stop,
+                        departure_from_stop.saturating_sub(duration),
+                        duration,
+                    ));
+                }
+            }
+
+            let mut more_marked_stops = Vec::new();
+            for (p_dash, to, tau, duration) in walks {
+                let time_to_beat = self.departure(p_dash, k).max(self.departure(ps, k));
+
+                if tau > time_to_beat {
+                    let label = Label {
+                        arrival: flip(tau),
+                        leg: Some(Leg::Walk {
+                            from: p_dash,
+                            to,
+                            duration,
+                        }),
+                    };
+                    self.labels.insert(p_dash, k, label);
+                    more_marked_stops.push(p_dash);
+                }
+            }
+
+            marked_stops.extend(more_marked_stops);
+
+            if marked_stops.is_empty() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The transit leg ridden next from `stop` in round `k`, after walking from there if the
+    /// label of `stop` is a footpath
+    fn next_transit(&self, stop: T::Stop, k: K) -> Option<Leg<T::Route, T::Trip, T::Stop>> {
+        let (round, label) = self.labels.get(stop, k)?;
+        let leg = match label.leg? {
+            Leg::Walk { to, .. } => self.labels.get(to, round)?.1.leg?,
+            leg => leg,
+        };
+
+        matches!(leg, Leg::Transit { .. }).then_some(leg)
+    }
+
+    /// Rebuilds the journey found in round `k`, following the legs from `ps` to `pt`
+    fn reconstruct_journey(
+        &self,
+        ps: T::Stop,
+        pt: T::Stop,
+        k: K,
+    ) -> Option<Journey<T::Route, T::Trip, T::Stop>> {
+        let mut legs = Vec::with_capacity(k);
+        let mut current = ps;
+        let mut inner_k = k;
+        let mut arrival = self.departure(ps, k);
+
+        while current != pt {
+            let (round, label) = self.labels.get(current, inner_k)?;
+            let leg = label.leg?;
+
+            match leg {
+                Leg::Transit {
+                    alight_stop,
+                    alight_time,
+                    ..
+                } => {
+                    current = alight_stop;
+                    arrival = alight_time;
+                    inner_k = round.checked_sub(1)?;
+                }
+                Leg::Walk { to, duration, .. } => {
+                    current = to;
+                    arrival = arrival.saturating_add(duration);
+                    inner_k = round;
+                }
+                Leg::Access { .. } | Leg::Egress { .. } => return None,
+            }
+
+            legs.push(leg);
+        }
+
+        if legs.is_empty() {
+            return None;
+        }
+
+        Some(Journey {
+            legs,
+            departure: self.departure(ps, k),
+            arrival,
+        })
+    }
+}
+
+/// The latest trip of `route` arriving at `stop` by `tau` that allows changing to `to`, the transit
+/// leg ridden next (see [`Timetable::get_trip_transfer_time`])
+fn latest_trip<T: ReverseTimetable + ?Sized>(
+    tt: &T,
+    route: T::Route,
+    stop: T::Stop,
+    tau: Tau,
+    to: Option<Leg<T::Route, T::Trip, T::Stop>>,
+) -> Result<Option<T::Trip>, Error> {
+    let Some(mut trip) = tt.get_latest_trip(route, tau, stop) else {
+        return Ok(None);
+    };
+    let Some(Leg::Transit {
+        route: to_route,
+        trip: to_trip,
+        board_stop: to_stop,
+        board_time,
+        ..
+    }) = to
+    else {
+        return Ok(Some(trip));
+    };
+
+    loop {
+        let arrival = tt.try_get_arrival_time(trip, stop)?;
+        let transfer_time =
+            tt.get_trip_transfer_time((route, trip, stop), (to_route, to_trip, to_stop));
+
+        let at = match transfer_time {
+            Some(time) if arrival.saturating_add(time) <= board_time => {
+                return Ok(Some(trip));
+            }
+            Some(time) => match board_time.checked_sub(time) {
+                Some(at) => at,
+                None => return Ok(None),
+            },
+            // forbidden, try the previous trip
+            None => match arrival.checked_sub(1) {
+                Some(at) => at,
+                None => return Ok(None),
+            },
+        };
+
+        trip = match tt.get_latest_trip(route, at, stop) {
+            Some(trip) => trip,
+            None => return Ok(None),
+        };
+    }
+}
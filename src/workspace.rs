@@ -0,0 +1,401 @@
+//! Dense, index-based search state, and reusing it across queries.
+//!
+//! Timetables whose stops and routes are indices ([`IndexedTimetable`]) can keep the state of a
+//! search in arrays instead of maps. A [`RaptorWorkspace`] holds these arrays, indexed by stop and
+//! by route, and is reset between queries by bumping a generation counter instead of clearing
+//! them. Keep one per thread, and run the queries of [`IndexedTimetable`] in it.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    Error, JourneyFor, K, Label, LabelStore, RaptorQuery, RouteQueue, Search, StopSet, Tau,
+    Timetable,
+};
+
+/// A [`Timetable`] whose stops are indices below [`IndexedTimetable::stop_count`], and routes
+/// indices below [`IndexedTimetable::route_count`]. Trips aren't indexed, the search never keys
+/// anything by trip.
+pub trait IndexedTimetable: Timetable<Stop: Into<usize>, Route: Into<usize>> {
+    fn stop_count(&self) -> usize;
+    fn route_count(&self) -> usize;
+
+    /// [`Timetable::raptor`], keeping the labels of the search in `workspace`
+    fn raptor_in(
+        &self,
+        workspace: &mut WorkspaceFor<Self>,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Vec<JourneyFor<Self>> {
+        self.try_raptor_in(workspace, transfers, tau, ps, pt)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// [`Timetable::try_raptor`], keeping the labels of the search in `workspace`
+    fn try_raptor_in(
+        &self,
+        workspace: &mut WorkspaceFor<Self>,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+        pt: Self::Stop,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        self.try_query_in(
+            workspace,
+            &RaptorQuery::new(ps, pt, tau).with_max_rounds(transfers),
+        )
+    }
+
+    /// [`Timetable::query`], keeping the labels of the search in `workspace`
+    fn query_in(
+        &self,
+        workspace: &mut WorkspaceFor<Self>,
+        query: &RaptorQuery<Self::Stop>,
+    ) -> Vec<JourneyFor<Self>> {
+        self.try_query_in(workspace, query)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// [`Timetable::try_query`], keeping the labels of the search in `workspace`
+    fn try_query_in(
+        &self,
+        workspace: &mut WorkspaceFor<Self>,
+        query: &RaptorQuery<Self::Stop>,
+    ) -> Result<Vec<JourneyFor<Self>>, Error> {
+        let RaptorQuery {
+            source: ps,
+            target: pt,
+            departure: tau,
+            ..
+        } = *query;
+        self.check_stop(ps)?;
+        self.check_stop(pt)?;
+
+        let targets = [(pt, 0)];
+        let mut search = workspace
+            .search(self, query.max_rounds, &targets)
+            .limit(query);
+
+        Ok(search
+            .run(ps, tau)?
+            .into_iter()
+            .filter_map(|k| search.reconstruct_journey(pt, tau, k))
+            .collect())
+    }
+
+    /// [`Timetable::earliest_arrivals`], keeping the labels of the search in `workspace`
+    fn earliest_arrivals_in(
+        &self,
+        workspace: &mut WorkspaceFor<Self>,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+    ) -> BTreeMap<Self::Stop, (Tau, K)> {
+        self.try_earliest_arrivals_in(workspace, transfers, tau, ps)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// [`Timetable::try_earliest_arrivals`], keeping the labels of the search in `workspace`
+    fn try_earliest_arrivals_in(
+        &self,
+        workspace: &mut WorkspaceFor<Self>,
+        transfers: usize,
+        tau: usize,
+        ps: Self::Stop,
+    ) -> Result<BTreeMap<Self::Stop, (Tau, K)>, Error> {
+        self.check_stop(ps)?;
+
+        let mut search = workspace.search(self, transfers, &[]);
+        search.run(ps, tau)?;
+
+        Ok(search
+            .labels
+            .best()
+            .map(|(stop, k, label)| (stop, (label.arrival, k)))
+            .collect())
+    }
+}
+
+/// [`RaptorWorkspace`] for the queries of timetable `T`
+pub type WorkspaceFor<T> =
+    RaptorWorkspace<<T as Timetable>::Route, <T as Timetable>::Trip, <T as Timetable>::Stop>;
+
+/// Labels, marked stops and route queue of a search, kept across queries. Sized for the stops
+/// and routes of a timetable, and grown when given a larger one.
+pub struct RaptorWorkspace<Route, Trip, Stop> {
+    labels: DenseLabels<Route, Trip, Stop>,
+    marked_stops: DenseStopSet<Stop>,
+    queue: DenseRouteQueue<Route, Stop>,
+}
+
+impl<Route, Trip, Stop> RaptorWorkspace<Route, Trip, Stop>
+where
+    Route: Ord + Copy + Into<usize>,
+    Trip: Copy,
+    Stop: Copy + Into<usize>,
+{
+    pub fn new(stop_count: usize, route_count: usize) -> Self {
+        Self {
+            labels: DenseLabels::new(stop_count),
+            marked_stops: DenseStopSet::new(stop_count),
+            queue: DenseRouteQueue::new(route_count),
+        }
+    }
+
+    pub fn for_timetable<T>(timetable: &T) -> Self
+    where
+        T: IndexedTimetable<Route = Route, Trip = Trip, Stop = Stop> + ?Sized,
+    {
+        Self::new(timetable.stop_count(), timetable.route_count())
+    }
+
+    /// A search over `timetable` in this workspace, with the labels of the last query dropped
+    pub(crate) fn search<'a, T>(
+        &'a mut self,
+        timetable: &'a T,
+        transfers: K,
+        targets: &'a [(Stop, Tau)],
+    ) -> DenseSearch<'a, T>
+    where
+        T: IndexedTimetable<Route = Route, Trip = Trip, Stop = Stop> + ?Sized,
+    {
+        let stop_count = timetable.stop_count();
+        self.labels.reset(stop_count);
+        self.marked_stops.reset(stop_count);
+        self.queue.reset(timetable.route_count());
+
+        Search::with_state(
+            timetable,
+            transfers,
+            targets,
+            &mut self.labels,
+            &mut self.marked_stops,
+            &mut self.queue,
+        )
+    }
+}
+
+// search keeping its state in a workspace
+type DenseSearch<'a, T> = Search<
+    'a,
+    T,
+    &'a mut DenseLabels<<T as Timetable>::Route, <T as Timetable>::Trip, <T as Timetable>::Stop>,
+    &'a mut DenseStopSet<<T as Timetable>::Stop>,
+    &'a mut DenseRouteQueue<<T as Timetable>::Route, <T as Timetable>::Stop>,
+>;
+
+// label of a stop in a round, with the generation it was found in
+type Slot<Route, Trip, Stop> = Option<(u32, Label<Route, Trip, Stop>)>;
+
+/// Labels of every round, each round an array indexed by stop. Labels of older generations are
+/// left in place and ignored.
+pub(crate) struct DenseLabels<Route, Trip, Stop> {
+    generation: u32,
+    rounds: Vec<Vec<Slot<Route, Trip, Stop>>>,
+    stop_count: usize,
+    // stops labelled in this generation, to list the best labels
+    reached: DenseStopSet<Stop>,
+}
+
+impl<Route, Trip, Stop> DenseLabels<Route, Trip, Stop>
+where
+    Route: Copy,
+    Trip: Copy,
+    Stop: Copy + Into<usize>,
+{
+    fn new(stop_count: usize) -> Self {
+        Self {
+            generation: 0,
+            rounds: Vec::new(),
+            stop_count,
+            reached: DenseStopSet::new(stop_count),
+        }
+    }
+
+    /// Drops every label, and makes room for `stop_count` stops
+    fn reset(&mut self, stop_count: usize) {
+        if stop_count > self.stop_count {
+            self.stop_count = stop_count;
+            for round in &mut self.rounds {
+                round.resize(stop_count, None);
+            }
+        }
+
+        self.generation = match self.generation.checked_add(1) {
+            Some(generation) => generation,
+            None => {
+                // labels of an old generation could pass for current ones after wrapping around
+                for round in &mut self.rounds {
+                    round.fill(None);
+                }
+                1
+            }
+        };
+        self.reached.reset(stop_count);
+    }
+
+    fn current(&self, round: K, stop: usize) -> Option<Label<Route, Trip, Stop>> {
+        match self.rounds.get(round)?.get(stop)? {
+            Some((generation, label)) if *generation == self.generation => Some(*label),
+            _ => None,
+        }
+    }
+}
+
+impl<Route, Trip, Stop> LabelStore<Route, Trip, Stop> for DenseLabels<Route, Trip, Stop>
+where
+    Route: Copy,
+    Trip: Copy,
+    Stop: Copy + Into<usize>,
+{
+    fn get(&self, stop: Stop, k: K) -> Option<(K, Label<Route, Trip, Stop>)> {
+        let stop = stop.into();
+        let last = k.min(self.rounds.len().checked_sub(1)?);
+
+        (0..=last)
+            .rev()
+            .find_map(|round| Some((round, self.current(round, stop)?)))
+    }
+
+    fn insert(&mut self, stop: Stop, k: K, label: Label<Route, Trip, Stop>) {
+        let idx = stop.into();
+
+        while self.rounds.len() <= k {
+            self.rounds.push(vec![None; self.stop_count]);
+        }
+
+        for round in k + 1..self.rounds.len() {
+            if self
+                .current(round, idx)
+                .is_some_and(|later| later.arrival >= label.arrival)
+            {
+                self.rounds[round][idx] = None;
+            }
+        }
+
+        self.rounds[k][idx] = Some((self.generation, label));
+        self.reached.insert(stop);
+    }
+
+    fn best(&self) -> impl Iterator<Item = (Stop, K, Label<Route, Trip, Stop>)> + '_ {
+        self.reached.iter().filter_map(|stop| {
+            let (round, label) = self.get(stop, K::MAX)?;
+            Some((stop, round, label))
+        })
+    }
+}
+
+/// Stops in the order they were inserted, along with the generation each stop was last inserted
+/// in to skip duplicates
+pub(crate) struct DenseStopSet<Stop> {
+    generation: u32,
+    generations: Vec<u32>,
+    stops: Vec<Stop>,
+}
+
+impl<Stop: Copy + Into<usize>> DenseStopSet<Stop> {
+    fn new(stop_count: usize) -> Self {
+        Self {
+            generation: 1,
+            generations: vec![0; stop_count],
+            stops: Vec::new(),
+        }
+    }
+
+    /// Empties the set, and makes room for `stop_count` stops
+    fn reset(&mut self, stop_count: usize) {
+        if stop_count > self.generations.len() {
+            self.generations.resize(stop_count, 0);
+        }
+        StopSet::clear(self);
+    }
+}
+
+impl<Stop: Copy + Into<usize>> StopSet<Stop> for DenseStopSet<Stop> {
+    fn insert(&mut self, stop: Stop) {
+        let idx = stop.into();
+
+        if self.generations[idx] != self.generation {
+            self.generations[idx] = self.generation;
+            self.stops.push(stop);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.stops.clear();
+        self.generation = match self.generation.checked_add(1) {
+            Some(generation) => generation,
+            None => {
+                self.generations.fill(0);
+                1
+            }
+        };
+    }
+
+    fn is_empty(&self) -> bool {
+        self.stops.is_empty()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Stop> + '_ {
+        self.stops.iter().copied()
+    }
+}
+
+/// Stop to scan every route from, indexed by route, along with the routes queued to clear and
+/// iterate them quickly
+pub(crate) struct DenseRouteQueue<Route, Stop> {
+    stops: Vec<Option<Stop>>,
+    routes: Vec<Route>,
+}
+
+impl<Route: Ord + Copy + Into<usize>, Stop: Copy> DenseRouteQueue<Route, Stop> {
+    fn new(route_count: usize) -> Self {
+        Self {
+            stops: vec![None; route_count],
+            routes: Vec::new(),
+        }
+    }
+
+    /// Empties the queue, and makes room for `route_count` routes
+    fn reset(&mut self, route_count: usize) {
+        RouteQueue::clear(self);
+        if route_count > self.stops.len() {
+            self.stops.resize(route_count, None);
+        }
+    }
+}
+
+impl<Route: Ord + Copy + Into<usize>, Stop: Copy> RouteQueue<Route, Stop>
+    for DenseRouteQueue<Route, Stop>
+{
+    fn get(&self, route: Route) -> Option<Stop> {
+        self.stops[route.into()]
+    }
+
+    fn insert(&mut self, route: Route, stop: Stop) {
+        let slot = &mut self.stops[route.into()];
+
+        if slot.is_none() {
+            self.routes.push(route);
+        }
+        *slot = Some(stop);
+    }
+
+    fn clear(&mut self) {
+        for &route in &self.routes {
+            self.stops[route.into()] = None;
+        }
+        self.routes.clear();
+    }
+
+    fn sort(&mut self) {
+        self.routes.sort_unstable();
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Route, Stop)> + '_ {
+        self.routes
+            .iter()
+            .filter_map(|&route| Some((route, self.stops[route.into()]?)))
+    }
+}